@@ -117,7 +117,7 @@ pub fn init_sip<S: AsRef<CStr>>(
         inv_cb.with_on_media_update(call_on_media_update);
 
         /* Initialize invite session module:  */
-        sip_endpt.init_inv_usage(&inv_cb)?;
+        sip_endpt.init_inv_usage(inv_cb)?;
     }
 
     let sip_endpt = Arc::new(sip_endpt);
@@ -235,7 +235,7 @@ pub enum Msg {
 type ModDataT = Weak<Mutex<Call>>;
 pub struct Call {
     pub id: String,
-    pub inv: Option<Arc<Mutex<pj::PjSipInvSession<ModDataT>>>>,
+    pub inv: Option<Arc<Mutex<pj::PjSipInvSession>>>,
     pub media: Arc<Mutex<MediaStream>>,
     pub wav_strmr: Option<pj::WavStreamer>,
     pub tx: flume::Sender<Msg>,
@@ -295,14 +295,6 @@ impl Call {
         /* Create the INVITE session. */
         let mut inv_sess = pj::PjSipInvSession::create_uac(&mut dialog, &sdp, 0)?;
 
-        /* Attach call data to invite session */
-        let mod_id = sip.sip_module.id();
-        if mod_id.is_negative() {
-            return Err(Error::Validation(format!(
-                "Did not register sip module: {mod_id}"
-            )));
-        }
-
         let (tx, rx) = flume::unbounded();
         let call = Call {
             id: call_id.clone(),
@@ -314,7 +306,8 @@ impl Call {
             timescale,
         };
         let call = Arc::new(Mutex::new(call));
-        inv_sess.insert_mod_data(mod_id as usize, Arc::downgrade(&call));
+        /* Attach call data to invite session */
+        inv_sess.set_mod_data(&sip.sip_module, Arc::downgrade(&call) as ModDataT)?;
         let inv_sess = Arc::new(Mutex::new(inv_sess));
         {
             let mut call = call.lock();
@@ -434,17 +427,10 @@ pub fn create_sdp<T, S: AsRef<CStr>>(
     Ok(sdp)
 }
 
-fn get_call(inv: &pj::PjSipInvSession<ModDataT>) -> Result<Arc<Mutex<Call>>, Error> {
+fn get_call(inv: &pj::PjSipInvSession) -> Result<Arc<Mutex<Call>>, Error> {
     let sip = get_sip()?;
 
-    let module_id = sip.sip_module.id();
-    if module_id.is_negative() {
-        return Err(Error::Validation(
-            "Sip module has a negative id, may not have been registered".into(),
-        ));
-    }
-
-    let call = match inv.get_mod_data(module_id as usize) {
+    let call = match inv.mod_data::<ModDataT>(&sip.sip_module) {
         Some(c) => match Weak::upgrade(&*c) {
             Some(c) => c,
             None => {
@@ -464,7 +450,7 @@ fn get_call(inv: &pj::PjSipInvSession<ModDataT>) -> Result<Arc<Mutex<Call>>, Err
     Ok(call)
 }
 
-fn call_on_state_changed(inv: &mut pj::PjSipInvSession<ModDataT>, _evt: &mut pj::PjSipEvent) {
+fn call_on_state_changed(inv: &mut pj::PjSipInvSession, _evt: &mut pj::PjSipEvent) {
     let inv_name = inv.obj_name().to_string_lossy().into_owned();
 
     let call = match get_call(inv) {
@@ -492,7 +478,7 @@ fn call_on_state_changed(inv: &mut pj::PjSipInvSession<ModDataT>, _evt: &mut pj:
     }
 }
 
-fn call_on_media_update(inv: &pj::PjSipInvSession<ModDataT>, status: pj::PjStatus) {
+fn call_on_media_update(inv: &pj::PjSipInvSession, status: pj::PjStatus) {
     let inv_name = inv.obj_name().to_string_lossy().into_owned();
     let call = match get_call(inv) {
         Ok(c) => c,
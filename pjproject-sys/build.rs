@@ -68,6 +68,17 @@ fn main() {
         conf.enable_shared();
     }
 
+    if env::var("CARGO_FEATURE_TLS").is_ok() {
+        // OPENSSL_DIR points to an OpenSSL that is not in the default paths
+        match env::var("OPENSSL_DIR") {
+            Ok(dir) => conf.with("ssl", Some(&dir)),
+            Err(_) => conf.enable("ssl", None),
+        };
+    } else {
+        conf.disable("ssl", None);
+    }
+    println!("cargo:rerun-if-env-changed=OPENSSL_DIR");
+
     if tracing::enabled!(tracing::Level::ERROR) {
         conf.cflag("-DPJ_LOG_MAX_LEVEL=1");
     } else if tracing::enabled!(tracing::Level::WARN) {
@@ -2,7 +2,7 @@ use std::ffi;
 
 use thiserror::Error;
 
-use crate::PjStatus;
+use crate::{PjStatus, PJ_EINVAL};
 
 #[derive(Debug, Error, Clone)]
 pub enum Error {
@@ -13,3 +13,13 @@ pub enum Error {
     #[error("{0}")]
     Validation(String),
 }
+
+impl Error {
+    /** Status to hand back to pjsip from a callback. */
+    pub(crate) fn status(&self) -> i32 {
+        match self {
+            Error::PjError(status) => status.0,
+            _ => PJ_EINVAL,
+        }
+    }
+}
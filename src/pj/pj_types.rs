@@ -103,3 +103,14 @@ impl Sub for PjTimeVal {
         Self(t)
     }
 }
+
+/** Copy a `pj_str_t` into a `String`. Strings pointing into a parsed
+ * message are not NUL terminated, so only `slen` bytes are read. */
+pub(crate) fn pj_str_to_string(s: &pj::pj_str_t) -> String {
+    if s.ptr.is_null() || s.slen <= 0 {
+        return String::new();
+    }
+
+    let bytes = unsafe { std::slice::from_raw_parts(s.ptr as *const u8, s.slen as usize) };
+    String::from_utf8_lossy(bytes).into_owned()
+}
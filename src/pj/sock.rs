@@ -1,13 +1,18 @@
 #![allow(non_camel_case_types)]
 use std::{
     ffi::{CStr, CString},
+    fmt::Display,
     marker::PhantomData,
+    os::raw::c_char,
 };
 
 use pjproject_sys as pj;
 
 use crate::{Error, PjStatus};
 
+/* Longest textual IPv6 address, without the terminating NUL */
+const PJ_INET6_ADDRSTRLEN: usize = 46;
+
 pub static PJ_AF_UNSPEC: &pj::pj_uint16_t = unsafe { &pj::PJ_AF_UNSPEC };
 pub static PJ_AF_UNIX: &pj::pj_uint16_t = unsafe { &pj::PJ_AF_UNIX };
 pub static PJ_AF_INET: &pj::pj_uint16_t = unsafe { &pj::PJ_AF_INET };
@@ -75,6 +80,23 @@ impl<'a> PjSockaddrRef<'a> {
     }
 }
 
+impl<'a> Display for PjSockaddrRef<'a> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut buf = [0 as c_char; PJ_INET6_ADDRSTRLEN + 10];
+        /* Flag 1 adds the port, 2 brackets an IPv6 address */
+        let addr = unsafe {
+            pj::pj_sockaddr_print(
+                self.as_ptr() as *const _,
+                buf.as_mut_ptr(),
+                buf.len() as _,
+                3,
+            )
+        };
+
+        write!(f, "{}", unsafe { CStr::from_ptr(addr) }.to_string_lossy())
+    }
+}
+
 impl<'a> From<&'a pj::pj_sockaddr> for PjSockaddrRef<'a> {
     fn from(value: &pj::pj_sockaddr) -> Self {
         Self {
@@ -403,7 +425,45 @@ pub enum SockaddrTRef<'a> {
     IPv6(PjSockaddrIn6Ref<'a>),
 }
 
+impl SockaddrT {
+    /** Address as the generic `pj_sockaddr` most pjlib calls take. */
+    pub fn as_ptr(&self) -> *const pj::pj_sockaddr {
+        match self {
+            SockaddrT::IPv4(a) => a.as_ptr() as *const _,
+            SockaddrT::IPv6(a) => a.as_ptr() as *const _,
+        }
+    }
+}
+
+impl<'a> From<&'a SockaddrT> for SockaddrTRef<'a> {
+    fn from(value: &'a SockaddrT) -> Self {
+        match value {
+            SockaddrT::IPv4(a) => SockaddrTRef::IPv4(a.as_ptr().into()),
+            SockaddrT::IPv6(a) => SockaddrTRef::IPv6(a.as_ptr().into()),
+        }
+    }
+}
+
+impl<'a> From<&PjSockaddrInRef<'a>> for SockaddrTRef<'a> {
+    fn from(value: &PjSockaddrInRef<'a>) -> Self {
+        SockaddrTRef::IPv4(value.as_ptr().into())
+    }
+}
+
+impl<'a> From<&PjSockaddrIn6Ref<'a>> for SockaddrTRef<'a> {
+    fn from(value: &PjSockaddrIn6Ref<'a>) -> Self {
+        SockaddrTRef::IPv6(value.as_ptr().into())
+    }
+}
+
 impl<'a> SockaddrTRef<'a> {
+    pub fn as_ptr(&self) -> *const pj::pj_sockaddr {
+        match self {
+            SockaddrTRef::IPv4(a) => a.as_ptr() as *const _,
+            SockaddrTRef::IPv6(a) => a.as_ptr() as *const _,
+        }
+    }
+
     pub fn get_port(&self) -> u16 {
         match self {
             SockaddrTRef::IPv4(a) => a.get_port(),
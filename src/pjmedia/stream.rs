@@ -79,8 +79,8 @@ unsafe impl Send for PjMediaStreamInfo {}
 unsafe impl Sync for PjMediaStreamInfo {}
 
 impl PjMediaStreamInfo {
-    pub fn from_sdp<T>(
-        inv_sess: &PjSipInvSession<T>,
+    pub fn from_sdp(
+        inv_sess: &PjSipInvSession,
         media_endpt: &PjMediaEndpt,
         local_sdp: &PjMediaSdpSessionRef,
         remote_sdp: &PjMediaSdpSessionRef,
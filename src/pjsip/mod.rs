@@ -1,17 +1,35 @@
+pub mod sip_auth;
 pub mod sip_dialog;
 pub mod sip_endpoint;
+pub mod sip_errno;
 pub mod sip_event;
 pub mod sip_module;
+pub mod sip_msg;
+pub mod sip_transaction;
 pub mod sip_transport;
+pub mod sip_transport_loop;
+pub mod sip_transport_tcp;
+#[cfg(feature = "tls")]
+pub mod sip_transport_tls;
 pub mod sip_transport_udp;
 pub mod sip_types;
 pub mod sip_ua_layer;
+pub mod sip_uri;
 
+pub use sip_auth::*;
 pub use sip_dialog::*;
 pub use sip_endpoint::*;
+pub use sip_errno::*;
 pub use sip_event::*;
 pub use sip_module::*;
+pub use sip_msg::*;
+pub use sip_transaction::*;
 pub use sip_transport::*;
+pub use sip_transport_loop::*;
+pub use sip_transport_tcp::*;
+#[cfg(feature = "tls")]
+pub use sip_transport_tls::*;
 pub use sip_transport_udp::*;
 pub use sip_types::*;
 pub use sip_ua_layer::*;
+pub use sip_uri::*;
@@ -0,0 +1,686 @@
+use std::{
+    collections::hash_map::RandomState,
+    ffi::{CStr, CString},
+    fmt::{Debug, Display},
+    hash::BuildHasher,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use parking_lot::{Mutex, RwLock};
+use pjproject_sys as pj;
+
+use crate::{
+    pj_str_to_string, Error, PjPool, PjSipEndpoint, PjSipRxData, PjSipTxData, PjStatus,
+    PJSIP_EAUTHACCNOTFOUND, PJSIP_EAUTHINNONCE, PJSIP_EAUTHINVALIDDIGEST, PJSIP_EAUTHNOAUTH,
+    PJSIP_EAUTHSTALECOUNT,
+};
+
+/** Digest algorithm of a credential. */
+#[derive(Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum PjSipAuthAlgorithm {
+    Md5,
+    Sha256,
+}
+
+impl From<PjSipAuthAlgorithm> for pj::pjsip_auth_algorithm_type {
+    fn from(value: PjSipAuthAlgorithm) -> Self {
+        match value {
+            PjSipAuthAlgorithm::Md5 => pj::pjsip_auth_algorithm_type_PJSIP_AUTH_ALGORITHM_MD5,
+            PjSipAuthAlgorithm::Sha256 => pj::pjsip_auth_algorithm_type_PJSIP_AUTH_ALGORITHM_SHA256,
+        }
+    }
+}
+
+impl Display for PjSipAuthAlgorithm {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                PjSipAuthAlgorithm::Md5 => "MD5",
+                PjSipAuthAlgorithm::Sha256 => "SHA-256",
+            }
+        )
+    }
+}
+
+impl Debug for PjSipAuthAlgorithm {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{self}[{}]", *self as u8)
+    }
+}
+
+/** Credential for digest authentication towards a server, e.g. a
+ * registrar or a proxy asking for it with 401 or 407. */
+#[derive(Clone)]
+pub struct PjSipCredInfo {
+    realm: CString,
+    scheme: CString,
+    username: CString,
+    data: CString,
+    data_type: pj::pjsip_cred_data_type,
+    algorithm: Option<PjSipAuthAlgorithm>,
+}
+
+impl PjSipCredInfo {
+    /** Credential with a plain text password. */
+    pub fn new<R: AsRef<CStr>, U: AsRef<CStr>, P: AsRef<CStr>>(
+        realm: R,
+        username: U,
+        password: P,
+    ) -> Self {
+        Self {
+            realm: realm.as_ref().to_owned(),
+            scheme: c"Digest".to_owned(),
+            username: username.as_ref().to_owned(),
+            data: password.as_ref().to_owned(),
+            data_type: pj::pjsip_cred_data_type_PJSIP_CRED_DATA_PLAIN_PASSWD,
+            algorithm: None,
+        }
+    }
+
+    /** Credential with the hash of `username:realm:password` in hex, HA1,
+     * instead of the password, so the password needn't be stored. The
+     * hash is MD5 unless set with `with_algorithm`. */
+    pub fn digest<R: AsRef<CStr>, U: AsRef<CStr>, H: AsRef<CStr>>(
+        realm: R,
+        username: U,
+        ha1: H,
+    ) -> Self {
+        Self {
+            data_type: pj::pjsip_cred_data_type_PJSIP_CRED_DATA_DIGEST,
+            ..Self::new(realm, username, ha1)
+        }
+    }
+
+    /** Credential used for whatever realm the server asks for. */
+    pub fn any_realm<U: AsRef<CStr>, P: AsRef<CStr>>(username: U, password: P) -> Self {
+        Self::new(c"*", username, password)
+    }
+
+    /** Only answer challenges for `algorithm`. A password credential
+     * answers any algorithm when none is set, a digest one MD5. */
+    pub fn with_algorithm(&mut self, algorithm: PjSipAuthAlgorithm) -> &mut Self {
+        self.algorithm = Some(algorithm);
+
+        self
+    }
+
+    pub fn realm(&self) -> &CStr {
+        &self.realm
+    }
+
+    pub fn username(&self) -> &CStr {
+        &self.username
+    }
+
+    /** pjsip view of the credential, it borrows the strings of `self`.
+     * pjsip copies credentials it is given, so it only needs to live for
+     * the call. */
+    pub(crate) fn as_pj(&self) -> pj::pjsip_cred_info {
+        let as_pj_str = |s: &CStr| unsafe { pj::pj_str(s.as_ptr() as *mut _) };
+
+        let mut cred = unsafe { std::mem::zeroed::<pj::pjsip_cred_info>() };
+        cred.realm = as_pj_str(&self.realm);
+        cred.scheme = as_pj_str(&self.scheme);
+        cred.username = as_pj_str(&self.username);
+        cred.data_type = self.data_type as _;
+        cred.data = as_pj_str(&self.data);
+        cred.algorithm_type = self.algorithm.map_or(
+            pj::pjsip_auth_algorithm_type_PJSIP_AUTH_ALGORITHM_NOT_SET,
+            Into::into,
+        );
+
+        cred
+    }
+}
+
+/* Copy the credential into `pool` for pjsip, for `realm` */
+unsafe fn copy_cred_info(
+    pool: *mut pj::pj_pool_t,
+    realm: &pj::pj_str_t,
+    credential: &PjSipCredInfo,
+    cred_info: &mut pj::pjsip_cred_info,
+) {
+    let cred = credential.as_pj();
+
+    pj::pj_strdup(pool, &mut cred_info.realm, realm);
+    pj::pj_strdup(pool, &mut cred_info.scheme, &cred.scheme);
+    pj::pj_strdup(pool, &mut cred_info.username, &cred.username);
+    pj::pj_strdup(pool, &mut cred_info.data, &cred.data);
+    cred_info.data_type = cred.data_type;
+    cred_info.algorithm_type = cred.algorithm_type;
+}
+
+impl Debug for PjSipCredInfo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        /* Never print the password */
+        write!(
+            f,
+            "PjSipCredInfo({}@{})",
+            self.username.to_string_lossy(),
+            self.realm.to_string_lossy()
+        )
+    }
+}
+
+/** Credentials to answer 401 and 407 challenges with, for requests outside
+ * of dialogs, see `PjSipEndpoint::send_request_with_auth`. Dialogs and
+ * registrations keep their own, see `PjSipDialog::set_credentials` and
+ * `PjSipRegistrationSetting::with_credential`.
+ *
+ * The session remembers the challenges it answered, so later requests to
+ * the same realm carry credentials up front. It keeps the endpoint alive,
+ * its memory comes from the endpoint's pool factory. */
+pub struct PjSipAuthClientSession {
+    /* pjsip keeps lists in the session, so it must not move */
+    pjsip_auth_clt_sess: Mutex<Box<pj::pjsip_auth_clt_sess>>,
+    pool: *mut pj::pj_pool_t,
+    endpt: Arc<PjSipEndpoint>,
+}
+
+unsafe impl Send for PjSipAuthClientSession {}
+unsafe impl Sync for PjSipAuthClientSession {}
+
+impl PjSipAuthClientSession {
+    pub fn new(endpt: Arc<PjSipEndpoint>, credentials: &[PjSipCredInfo]) -> Result<Self, Error> {
+        let pool = unsafe {
+            pj::pjsip_endpt_create_pool(endpt.as_mut_ptr(), c"auth_clt".as_ptr(), 1024, 1024)
+        };
+        if pool.is_null() {
+            return Err(Error::Validation("Failed to create pool".into()));
+        }
+
+        let mut sess = Box::new(unsafe { std::mem::zeroed::<pj::pjsip_auth_clt_sess>() });
+        let status = unsafe { pj::pjsip_auth_clt_init(&mut *sess, endpt.as_mut_ptr(), pool, 0) };
+        if let Err(err) = PjStatus::result_for_status(status) {
+            unsafe { pj::pjsip_endpt_release_pool(endpt.as_mut_ptr(), pool) };
+            return Err(err);
+        }
+
+        let session = Self {
+            pjsip_auth_clt_sess: Mutex::new(sess),
+            pool,
+            endpt,
+        };
+        session.set_credentials(credentials)?;
+
+        Ok(session)
+    }
+
+    /** Replace the credentials of the session. */
+    pub fn set_credentials(&self, credentials: &[PjSipCredInfo]) -> Result<(), Error> {
+        let mut sess = self.pjsip_auth_clt_sess.lock();
+
+        unsafe { set_credentials(&mut **sess, credentials) }
+    }
+
+    /** Add the credentials of earlier challenges to a new request, so it
+     * needn't be challenged again. */
+    pub fn init_req(&self, tdata: &mut PjSipTxData) -> Result<(), Error> {
+        let mut sess = self.pjsip_auth_clt_sess.lock();
+        let status = unsafe { pj::pjsip_auth_clt_init_req(&mut **sess, tdata.as_mut_ptr()) };
+
+        PjStatus::result_for_status(status)
+    }
+
+    /** Create a copy of `request` with the answer to the challenge in the
+     * 401 or 407 `rdata`, with a new branch and CSeq. Fails when there is
+     * no credential for the realm, or the same credentials were already
+     * rejected. */
+    pub fn reinit_req(
+        &self,
+        rdata: &PjSipRxData,
+        request: &mut PjSipTxData,
+    ) -> Result<PjSipTxData, Error> {
+        let mut sess = self.pjsip_auth_clt_sess.lock();
+
+        let mut pjsip_tx_data = std::ptr::null_mut();
+        let status = unsafe {
+            pj::pjsip_auth_clt_reinit_req(
+                &mut **sess,
+                rdata.as_ptr(),
+                request.as_mut_ptr(),
+                &mut pjsip_tx_data,
+            )
+        };
+        PjStatus::result_for_status(status)?;
+
+        if pjsip_tx_data.is_null() {
+            return Err(Error::Validation("Got null creating PjSipTxData".into()));
+        }
+
+        Ok(PjSipTxData::from(pjsip_tx_data))
+    }
+}
+
+impl Drop for PjSipAuthClientSession {
+    fn drop(&mut self) {
+        unsafe {
+            pj::pjsip_auth_clt_deinit(&mut **self.pjsip_auth_clt_sess.get_mut());
+            pj::pjsip_endpt_release_pool(self.endpt.as_mut_ptr(), self.pool);
+        }
+    }
+}
+
+pub(crate) unsafe fn set_credentials(
+    sess: *mut pj::pjsip_auth_clt_sess,
+    credentials: &[PjSipCredInfo],
+) -> Result<(), Error> {
+    /* pjsip copies the credentials to the session's pool */
+    let credentials = credentials
+        .iter()
+        .map(PjSipCredInfo::as_pj)
+        .collect::<Vec<_>>();
+    let status =
+        pj::pjsip_auth_clt_set_credentials(sess, credentials.len() as _, credentials.as_ptr());
+
+    PjStatus::result_for_status(status)
+}
+
+/** Outcome of checking the credentials of a request. */
+#[derive(Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum PjSipAuthStatus {
+    Ok,
+    /** No credentials for our realm, challenge the request. */
+    Missing,
+    /** The credentials answer a challenge that expired or that we did not
+     * send, challenge again with `stale` so the client retries without
+     * asking its user. */
+    Stale,
+    WrongPassword,
+    UnknownUser,
+    /** Malformed credentials or an unsupported algorithm. */
+    Invalid,
+}
+
+impl From<u8> for PjSipAuthStatus {
+    fn from(value: u8) -> Self {
+        match value {
+            0 => Self::Ok,
+            1 => Self::Missing,
+            2 => Self::Stale,
+            3 => Self::WrongPassword,
+            4 => Self::UnknownUser,
+            _ => Self::Invalid,
+        }
+    }
+}
+
+impl Display for PjSipAuthStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                PjSipAuthStatus::Ok => "Ok",
+                PjSipAuthStatus::Missing => "Missing",
+                PjSipAuthStatus::Stale => "Stale",
+                PjSipAuthStatus::WrongPassword => "WrongPassword",
+                PjSipAuthStatus::UnknownUser => "UnknownUser",
+                PjSipAuthStatus::Invalid => "Invalid",
+            }
+        )
+    }
+}
+
+impl Debug for PjSipAuthStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{self}[{}]", *self as u8)
+    }
+}
+
+/** Looks up the credential of a user, by realm and username. Returning a
+ * digest credential means the password needn't be stored. */
+type OnLookup = Arc<dyn Fn(&str, &str) -> Option<PjSipCredInfo> + Send + Sync>;
+
+/* pjsip gives the lookup no user data, so like TLS verify callbacks every
+ * server takes a slot with its own trampoline. */
+const LOOKUP_SLOT_COUNT: usize = 8;
+
+#[allow(clippy::declare_interior_mutable_const)]
+const LOOKUP_SLOT_INIT: RwLock<Option<OnLookup>> = parking_lot::const_rwlock(None);
+static LOOKUP_SLOTS: [RwLock<Option<OnLookup>>; LOOKUP_SLOT_COUNT] =
+    [LOOKUP_SLOT_INIT; LOOKUP_SLOT_COUNT];
+
+type LookupTrampoline = unsafe extern "C" fn(
+    *mut pj::pj_pool_t,
+    *const pj::pjsip_auth_lookup_cred_param,
+    *mut pj::pjsip_cred_info,
+) -> pj::pj_status_t;
+
+static ON_LOOKUP: [LookupTrampoline; LOOKUP_SLOT_COUNT] = [
+    on_lookup::<0>,
+    on_lookup::<1>,
+    on_lookup::<2>,
+    on_lookup::<3>,
+    on_lookup::<4>,
+    on_lookup::<5>,
+    on_lookup::<6>,
+    on_lookup::<7>,
+];
+
+unsafe extern "C" fn on_lookup<const N: usize>(
+    pool: *mut pj::pj_pool_t,
+    param: *const pj::pjsip_auth_lookup_cred_param,
+    cred_info: *mut pj::pjsip_cred_info,
+) -> pj::pj_status_t {
+    /* Don't hold the slot lock while the callback runs */
+    let Some(cb) = LOOKUP_SLOTS[N].read().clone() else {
+        return PJSIP_EAUTHACCNOTFOUND;
+    };
+    let param = &*param;
+
+    match cb(
+        &pj_str_to_string(&param.realm),
+        &pj_str_to_string(&param.acc_name),
+    ) {
+        Some(credential) => {
+            copy_cred_info(pool, &param.realm, &credential, &mut *cred_info);
+
+            pj::pj_constants__PJ_SUCCESS as _
+        }
+        None => PJSIP_EAUTHACCNOTFOUND,
+    }
+}
+
+/** Digest authentication of incoming requests, e.g. in a module's
+ * on_rx_request. Requests without valid credentials are answered with the
+ * response `challenge` creates:
+ *
+ * - `Missing` or `Stale`: 401 (407 for a proxy) with `challenge`, `stale`
+ *   set for `Stale`.
+ * - `WrongPassword` or `UnknownUser`: a new challenge or 403.
+ *
+ * pjsip itself accepts any nonce, so the server signs the nonces it sends
+ * and reports answers to others or expired ones as `Stale`. */
+pub struct PjSipAuthServer {
+    /* pjsip keeps a pointer to the realm, which lives in the pool */
+    pjsip_auth_srv: Box<pj::pjsip_auth_srv>,
+    #[allow(dead_code)]
+    pool: PjPool,
+    realm: String,
+    is_proxy: bool,
+    lookup_slot: usize,
+    nonces: Nonces,
+}
+
+unsafe impl Send for PjSipAuthServer {}
+unsafe impl Sync for PjSipAuthServer {}
+
+impl PjSipAuthServer {
+    /** Authenticate requests for `realm`, as a proxy with 407 and
+     * Proxy-Authorization when `is_proxy`, otherwise with 401 and
+     * Authorization. `lookup` gets the realm and username and returns the
+     * user's credential, it runs on the thread calling `verify`. */
+    pub fn new<S: AsRef<CStr>, F>(realm: S, is_proxy: bool, lookup: F) -> Result<Self, Error>
+    where
+        F: Fn(&str, &str) -> Option<PjSipCredInfo> + Send + Sync + 'static,
+    {
+        let lookup: OnLookup = Arc::new(lookup);
+        let lookup_slot = LOOKUP_SLOTS
+            .iter()
+            .position(|slot| {
+                let mut slot = slot.write();
+                if slot.is_some() {
+                    return false;
+                }
+                slot.replace(lookup.clone());
+
+                true
+            })
+            .ok_or_else(|| Error::Validation("No free auth server lookup slots".into()))?;
+
+        let mut pool = PjPool::default_with_name(c"auth_srv");
+        let mut pjsip_auth_srv = Box::new(unsafe { std::mem::zeroed::<pj::pjsip_auth_srv>() });
+        let param = pj::pjsip_auth_srv_init_param {
+            realm: &unsafe { pj::pj_str(realm.as_ref().as_ptr() as *mut _) },
+            lookup2: Some(ON_LOOKUP[lookup_slot]),
+            options: if is_proxy {
+                pj::PJSIP_AUTH_SRV_IS_PROXY
+            } else {
+                0
+            },
+        };
+        let status =
+            unsafe { pj::pjsip_auth_srv_init2(pool.as_mut_ptr(), &mut *pjsip_auth_srv, &param) };
+        if let Err(err) = PjStatus::result_for_status(status) {
+            LOOKUP_SLOTS[lookup_slot].write().take();
+            return Err(err);
+        }
+
+        Ok(Self {
+            pjsip_auth_srv,
+            pool,
+            realm: realm.as_ref().to_string_lossy().into_owned(),
+            is_proxy,
+            lookup_slot,
+            nonces: Nonces::new(Duration::from_secs(300)),
+        })
+    }
+
+    /** How long the answer to a challenge is accepted, 5 minutes unless
+     * set. Later requests with the same credentials get `Stale`. */
+    pub fn set_nonce_lifetime(&mut self, lifetime: Duration) {
+        self.nonces.lifetime = lifetime;
+    }
+
+    pub fn realm(&self) -> &str {
+        &self.realm
+    }
+
+    /** Status code of a challenge, 407 for a proxy and 401 otherwise. */
+    pub fn challenge_code(&self) -> u16 {
+        if self.is_proxy {
+            407
+        } else {
+            401
+        }
+    }
+
+    /** Check the credentials of the request in `rdata`. */
+    pub fn verify(&self, rdata: &mut PjSipRxData) -> PjSipAuthStatus {
+        let mut status_code = 0;
+        let status = unsafe {
+            pj::pjsip_auth_srv_verify(
+                &*self.pjsip_auth_srv as *const _ as *mut _,
+                rdata.as_mut_ptr(),
+                &mut status_code,
+            )
+        };
+
+        match status {
+            s if s == pj::pj_constants__PJ_SUCCESS as pj::pj_status_t => {}
+            PJSIP_EAUTHNOAUTH => return PjSipAuthStatus::Missing,
+            PJSIP_EAUTHACCNOTFOUND => return PjSipAuthStatus::UnknownUser,
+            PJSIP_EAUTHINVALIDDIGEST => return PjSipAuthStatus::WrongPassword,
+            PJSIP_EAUTHSTALECOUNT | PJSIP_EAUTHINNONCE => return PjSipAuthStatus::Stale,
+            _ => return PjSipAuthStatus::Invalid,
+        }
+
+        match self.answered_nonce(rdata) {
+            Some(nonce) if self.nonces.is_valid(&nonce) => PjSipAuthStatus::Ok,
+            _ => PjSipAuthStatus::Stale,
+        }
+    }
+
+    /** Add a challenge with a new nonce to `tdata`, a response to the
+     * request with `challenge_code`. */
+    pub fn challenge(&self, tdata: &mut PjSipTxData, stale: bool) -> Result<(), Error> {
+        self.challenge_with(tdata, stale, PjSipAuthAlgorithm::Md5)
+    }
+
+    /** Same as `challenge` for a digest algorithm other than MD5. Add a
+     * challenge for each algorithm to offer several. */
+    pub fn challenge_with(
+        &self,
+        tdata: &mut PjSipTxData,
+        stale: bool,
+        algorithm: PjSipAuthAlgorithm,
+    ) -> Result<(), Error> {
+        let nonce = CString::new(self.nonces.create())?;
+        let status = unsafe {
+            pj::pjsip_auth_srv_challenge2(
+                &*self.pjsip_auth_srv as *const _ as *mut _,
+                &pj::pj_str(c"auth".as_ptr() as *mut _),
+                &pj::pj_str(nonce.as_ptr() as *mut _),
+                std::ptr::null(),
+                stale as _,
+                tdata.as_mut_ptr(),
+                algorithm.into(),
+            )
+        };
+
+        PjStatus::result_for_status(status)
+    }
+
+    /* Nonce of the Digest credentials for our realm, the ones pjsip
+     * verified */
+    fn answered_nonce(&self, rdata: &PjSipRxData) -> Option<String> {
+        let hdr_type = if self.is_proxy {
+            pj::pjsip_hdr_e_PJSIP_H_PROXY_AUTHORIZATION
+        } else {
+            pj::pjsip_hdr_e_PJSIP_H_AUTHORIZATION
+        };
+        let msg = rdata.as_ref().msg_info.msg;
+
+        let mut start = std::ptr::null();
+        loop {
+            let hdr = unsafe { pj::pjsip_msg_find_hdr(msg, hdr_type, start) }
+                as *const pj::pjsip_authorization_hdr;
+            if hdr.is_null() {
+                return None;
+            }
+            let hdr = unsafe { &*hdr };
+
+            if pj_str_to_string(&hdr.scheme).eq_ignore_ascii_case("Digest") {
+                let digest = unsafe { &hdr.credential.digest };
+                if pj_str_to_string(&digest.realm).eq_ignore_ascii_case(&self.realm) {
+                    return Some(pj_str_to_string(&digest.nonce));
+                }
+            }
+            start = hdr.next as *const _;
+        }
+    }
+}
+
+impl Drop for PjSipAuthServer {
+    fn drop(&mut self) {
+        LOOKUP_SLOTS[self.lookup_slot].write().take();
+    }
+}
+
+/* Nonces that need no state: the time of issue and a counter, signed
+ * with a key of the server. */
+struct Nonces {
+    key: RandomState,
+    started: Instant,
+    count: AtomicU64,
+    lifetime: Duration,
+}
+
+impl Nonces {
+    fn new(lifetime: Duration) -> Self {
+        Self {
+            key: RandomState::new(),
+            started: Instant::now(),
+            count: AtomicU64::new(0),
+            lifetime,
+        }
+    }
+
+    fn create(&self) -> String {
+        let issued = self.now();
+        let count = self.count.fetch_add(1, Ordering::Relaxed);
+
+        format!("{issued:016x}{count:016x}{}", self.mac(issued, count))
+    }
+
+    fn is_valid(&self, nonce: &str) -> bool {
+        let Some((issued, count, mac)) = parse_nonce(nonce) else {
+            return false;
+        };
+
+        mac == self.mac(issued, count)
+            && self.now().saturating_sub(issued) < self.lifetime.as_millis() as u64
+    }
+
+    /* Milliseconds since the server was created */
+    fn now(&self) -> u64 {
+        self.started.elapsed().as_millis() as u64
+    }
+
+    fn mac(&self, issued: u64, count: u64) -> String {
+        format!(
+            "{:016x}{:016x}",
+            self.key.hash_one((issued, count, 0u8)),
+            self.key.hash_one((issued, count, 1u8))
+        )
+    }
+}
+
+/* Split a nonce into the time of issue, the counter and the MAC */
+fn parse_nonce(nonce: &str) -> Option<(u64, u64, &str)> {
+    if nonce.len() != 64 || !nonce.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+    let issued = u64::from_str_radix(&nonce[..16], 16).ok()?;
+    let count = u64::from_str_radix(&nonce[16..32], 16).ok()?;
+
+    Some((issued, count, &nonce[32..]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nonce_is_valid() {
+        let nonces = Nonces::new(Duration::from_secs(300));
+        let nonce = nonces.create();
+
+        assert!(nonces.is_valid(&nonce));
+        assert_ne!(nonce, nonces.create());
+    }
+
+    #[test]
+    fn nonce_expires() {
+        let nonces = Nonces::new(Duration::ZERO);
+
+        assert!(!nonces.is_valid(&nonces.create()));
+    }
+
+    #[test]
+    fn nonce_of_other_server() {
+        let nonces = Nonces::new(Duration::from_secs(300));
+
+        assert!(!nonces.is_valid(&Nonces::new(Duration::from_secs(300)).create()));
+    }
+
+    #[test]
+    fn nonce_tampered() {
+        let nonces = Nonces::new(Duration::from_secs(300));
+        let nonce = nonces.create();
+
+        /* A later time of issue */
+        let later = format!("{:016x}{}", nonces.now() + 1000, &nonce[16..]);
+        assert!(!nonces.is_valid(&later));
+        assert!(!nonces.is_valid(&nonce[..63]));
+        assert!(!nonces.is_valid(&format!("{}g", &nonce[..63])));
+        assert!(!nonces.is_valid(""));
+    }
+
+    #[test]
+    fn parse_nonce_parts() {
+        let nonce = format!("{:016x}{:016x}{}", 42, 7, "ab".repeat(16));
+
+        assert_eq!(parse_nonce(&nonce), Some((42, 7, "ab".repeat(16).as_str())));
+        assert_eq!(parse_nonce(&nonce.replacen('0', "+", 1)), None);
+    }
+}
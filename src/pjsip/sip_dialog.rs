@@ -1,9 +1,21 @@
-use std::ffi::CStr;
+use std::{
+    any::Any,
+    ffi::{CStr, CString},
+    fmt::{Debug, Display},
+    sync::Arc,
+};
 
 use pjproject_sys as pj;
 
-use crate::{Error, PjPoolRef, PjSipUserAgentRef, PjStatus};
+use crate::{
+    get_mod_data, pj_str_to_string, remove_mod_data, set_credentials, set_mod_data, Error,
+    PjPoolRef, PjSipCredInfo, PjSipModule, PjSipNameAddr, PjSipRole, PjSipRxData, PjSipTpSelector,
+    PjSipTransaction, PjSipTxData, PjSipUri, PjSipUriRef, PjSipUserAgentRef, PjStatus,
+};
 
+/** A dialog of the UA layer. Every handle holds a session count on the
+ * dialog, so pjsip keeps it alive while Rust holds it, and cloning is
+ * cheap. */
 pub struct PjSipDialog {
     dialog: *mut pj::pjsip_dialog,
 }
@@ -28,22 +40,433 @@ impl PjSipDialog {
             )
         };
 
-        PjStatus::result_for_status(status).map(|_| Self { dialog })
+        PjStatus::result_for_status(status).map(|_| Self::from(dialog))
+    }
+
+    /** Create the dialog for an incoming dialog-creating request, e.g. an
+     * INVITE. `contact` defaults to the request URI.
+     *
+     * The dialog comes back locked, drop the lock once it is set up, e.g.
+     * with a usage or an invite session. */
+    pub fn create_uas<S: AsRef<CStr>>(
+        ua: PjSipUserAgentRef,
+        rdata: &mut PjSipRxData,
+        contact: Option<S>,
+    ) -> Result<(Self, PjSipDialogLock), Error> {
+        let contact =
+            contact.map(|contact| unsafe { pj::pj_str(contact.as_ref().as_ptr() as *mut i8) });
+        let mut dialog = std::ptr::null_mut();
+        let status = unsafe {
+            pj::pjsip_dlg_create_uas_and_inc_lock(
+                ua.as_ptr() as *mut _,
+                rdata.as_mut_ptr(),
+                contact
+                    .as_ref()
+                    .map_or(std::ptr::null(), |contact| contact as *const _),
+                &mut dialog,
+            )
+        };
+
+        PjStatus::result_for_status(status)
+            .map(|_| (Self::from(dialog), PjSipDialogLock { dialog }))
+    }
+
+    /** Dialog an incoming in-dialog request or response belongs to. */
+    pub fn from_rx_data(rdata: &mut PjSipRxData) -> Option<Self> {
+        let dialog = unsafe { pj::pjsip_rdata_get_dlg(rdata.as_mut_ptr()) };
+
+        (!dialog.is_null()).then(|| Self::from(dialog))
+    }
+
+    pub fn as_ptr(&self) -> *const pj::pjsip_dialog {
+        self.dialog
     }
 
     pub fn as_mut_ptr(&mut self) -> *mut pj::pjsip_dialog {
         self.dialog
     }
 
-    /** Forcefully terminate dialog. Dialog may have already been destroyed
-     * and this will return an error if so. Should be ok to ignore the error */
+    pub fn as_ref(&self) -> &pj::pjsip_dialog {
+        unsafe { &*self.dialog }
+    }
+
+    /** Lock the dialog until the guard is dropped. The dialog is not
+     * destroyed while locked. */
+    pub fn inc_lock(&self) -> PjSipDialogLock {
+        unsafe { pj::pjsip_dlg_inc_lock(self.dialog) };
+
+        PjSipDialogLock {
+            dialog: self.dialog,
+        }
+    }
+
+    /** Lock the dialog if no other thread holds the lock. */
+    pub fn try_inc_lock(&self) -> Option<PjSipDialogLock> {
+        let status = unsafe { pj::pjsip_dlg_try_inc_lock(self.dialog) };
+
+        PjStatus::result_for_status(status)
+            .ok()
+            .map(|_| PjSipDialogLock {
+                dialog: self.dialog,
+            })
+    }
+
+    pub fn call_id(&self) -> String {
+        pj_str_to_string(unsafe { &(*self.as_ref().call_id).id })
+    }
+
+    pub fn local_tag(&self) -> String {
+        pj_str_to_string(unsafe { &(*self.as_ref().local.info).tag })
+    }
+
+    /** Tag of the remote party, empty until a UAC dialog gets a response
+     * with one. */
+    pub fn remote_tag(&self) -> String {
+        pj_str_to_string(unsafe { &(*self.as_ref().remote.info).tag })
+    }
+
+    /** Local URI with display name, as in the From header of our
+     * requests. */
+    pub fn local_uri(&self) -> Result<PjSipNameAddr, Error> {
+        fromto_name_addr(self.as_ref().local.info)
+    }
+
+    /** Remote URI with display name, as in the To header of our
+     * requests. */
+    pub fn remote_uri(&self) -> Result<PjSipNameAddr, Error> {
+        fromto_name_addr(self.as_ref().remote.info)
+    }
+
+    /** Where requests in the dialog go, the remote Contact once known. */
+    pub fn remote_target(&self) -> Result<PjSipUri, Error> {
+        PjSipUri::parse(PjSipUriRef::from(self.as_ref().target as *const _).to_cstring())
+    }
+
+    /** CSeq of the last request we sent in the dialog. */
+    pub fn local_cseq(&self) -> i32 {
+        self.as_ref().local.cseq
+    }
+
+    /** CSeq of the last request we received in the dialog. */
+    pub fn remote_cseq(&self) -> i32 {
+        self.as_ref().remote.cseq
+    }
+
+    /** Route set in the order requests traverse it, from Record-Route. */
+    pub fn route_set(&self) -> Result<Vec<PjSipNameAddr>, Error> {
+        let head = &self.as_ref().route_set as *const pj::pjsip_route_hdr;
+        let mut routes = Vec::new();
+        let mut route = unsafe { (*head).next } as *const pj::pjsip_route_hdr;
+        while !std::ptr::eq(route, head) {
+            let name_addr = unsafe { &(*route).name_addr } as *const _ as *const pj::pjsip_uri;
+            let printed = PjSipUriRef::from(name_addr)
+                .print(pj::pjsip_uri_context_e_PJSIP_URI_IN_ROUTING_HDR);
+            routes.push(PjSipNameAddr::parse(CString::new(printed)?)?);
+            route = unsafe { (*route).next };
+        }
+
+        Ok(routes)
+    }
+
+    pub fn state(&self) -> PjSipDialogState {
+        (self.as_ref().state as u8).into()
+    }
+
+    pub fn role(&self) -> PjSipRole {
+        (self.as_ref().role as u8).into()
+    }
+
+    /** Whether the dialog runs over sips: or TLS. */
+    pub fn is_secure(&self) -> bool {
+        self.as_ref().secure != 0
+    }
+
+    /** Number of modules registered as usages of the dialog. */
+    pub fn usage_count(&self) -> u32 {
+        self.as_ref().usage_cnt as _
+    }
+
+    /** Number of sessions, e.g. invite sessions, holding the dialog. */
+    pub fn session_count(&self) -> i32 {
+        self.as_ref().sess_count as _
+    }
+
+    /** Release the dialog, destroying it right away when no other handle,
+     * session or transaction holds it. Fails, like pjsip does, when the
+     * dialog is still in use, it is then destroyed once released. */
     pub fn terminate(self) -> Result<(), Error> {
-        let status = unsafe { pj::pjsip_dlg_terminate(self.dialog) };
+        let in_use = {
+            let _lock = self.inc_lock();
+            self.as_ref().sess_count > 1 || self.as_ref().tsx_count > 0
+        };
+        /* Dropping the last session count destroys the dialog */
+        drop(self);
+
+        if in_use {
+            return Err(Error::Validation("Dialog is still in use".into()));
+        }
+
+        Ok(())
+    }
+
+    /** Send all requests and responses of the dialog over the selected
+     * transport or listener. */
+    pub fn set_transport(&mut self, selector: &PjSipTpSelector) -> Result<(), Error> {
+        let status = unsafe { pj::pjsip_dlg_set_transport(self.dialog, selector.as_ptr()) };
+
+        PjStatus::result_for_status(status)
+    }
+
+    /** Register `module` as a usage of the dialog. Its on_rx_request and
+     * on_rx_response get the messages of the dialog and its on_tsx_state
+     * the progress of the dialog's transactions, e.g. the response to a
+     * request sent with `send_request`. */
+    pub fn add_usage(&mut self, module: &PjSipModule) -> Result<(), Error> {
+        let status = unsafe {
+            pj::pjsip_dlg_add_usage(self.dialog, module.as_mut_ptr(), std::ptr::null_mut())
+        };
+
+        PjStatus::result_for_status(status)
+    }
+
+    pub fn has_usage(&self, module: &PjSipModule) -> bool {
+        unsafe { pj::pjsip_dlg_has_usage(self.dialog, module.as_mut_ptr()) != 0 }
+    }
+
+    /** Create a request within the dialog, e.g. `INFO` or `MESSAGE`, with
+     * the dialog's Call-ID, tags, next CSeq, target and route set. */
+    pub fn create_request<M: AsRef<CStr>>(&mut self, method: M) -> Result<PjSipTxData, Error> {
+        let mut pjsip_method = unsafe { std::mem::zeroed::<pj::pjsip_method>() };
+        let mut method = unsafe { pj::pj_str(method.as_ref().as_ptr() as *mut _) };
+        unsafe { pj::pjsip_method_init_np(&mut pjsip_method, &mut method) };
+
+        let mut pjsip_tx_data = std::ptr::null_mut();
+        let status = unsafe {
+            pj::pjsip_dlg_create_request(self.dialog, &pjsip_method, -1, &mut pjsip_tx_data)
+        };
+        PjStatus::result_for_status(status)?;
+
+        if pjsip_tx_data.is_null() {
+            return Err(Error::Validation("Got null creating PjSipTxData".into()));
+        }
+
+        Ok(PjSipTxData::from(pjsip_tx_data))
+    }
+
+    /** Send a request created with `create_request` in a new client
+     * transaction. The response is reported to the dialog's usages, see
+     * `add_usage`, and `PjSipTransaction::dialog` tells which dialog it
+     * belongs to. */
+    pub fn send_request(&mut self, mut tdata: PjSipTxData) -> Result<(), Error> {
+        let status = unsafe {
+            pj::pjsip_dlg_send_request(self.dialog, tdata.as_mut_ptr(), -1, std::ptr::null_mut())
+        };
+
+        PjStatus::result_for_status(status)
+    }
+
+    /** Create a response to an in-dialog request, with the dialog's To
+     * tag. */
+    pub fn create_response<S: AsRef<CStr>>(
+        &mut self,
+        rdata: &mut PjSipRxData,
+        status_code: u16,
+        reason: Option<S>,
+    ) -> Result<PjSipTxData, Error> {
+        let reason = reason.map(|r| unsafe { pj::pj_str(r.as_ref().as_ptr() as *mut _) });
+
+        let mut pjsip_tx_data = std::ptr::null_mut();
+        let status = unsafe {
+            pj::pjsip_dlg_create_response(
+                self.dialog,
+                rdata.as_mut_ptr(),
+                status_code as _,
+                reason.as_ref().map_or(std::ptr::null(), |r| r as *const _),
+                &mut pjsip_tx_data,
+            )
+        };
+        PjStatus::result_for_status(status)?;
+
+        if pjsip_tx_data.is_null() {
+            return Err(Error::Validation("Got null creating PjSipTxData".into()));
+        }
+
+        Ok(PjSipTxData::from(pjsip_tx_data))
+    }
+
+    /** Send a response on the server transaction the dialog created for
+     * the request in `rdata`. */
+    pub fn send_response(
+        &mut self,
+        rdata: &mut PjSipRxData,
+        mut tdata: PjSipTxData,
+    ) -> Result<(), Error> {
+        let Some(mut tsx) = PjSipTransaction::from_rx_data(rdata) else {
+            unsafe { pj::pjsip_tx_data_dec_ref(tdata.as_mut_ptr()) };
+            return Err(Error::Validation(
+                "Request has no transaction to respond on".into(),
+            ));
+        };
+
+        let status = unsafe {
+            pj::pjsip_dlg_send_response(self.dialog, tsx.as_mut_ptr(), tdata.as_mut_ptr())
+        };
 
         PjStatus::result_for_status(status)
     }
 
+    /** Answer an in-dialog request, e.g. 200 to an INFO carrying DTMF.
+     * `body` is a content type, `type/subtype`, and the body data. */
+    pub fn respond<S: AsRef<CStr>>(
+        &mut self,
+        rdata: &mut PjSipRxData,
+        status_code: u16,
+        reason: Option<S>,
+        headers: &[(&CStr, &CStr)],
+        body: Option<(&CStr, &[u8])>,
+    ) -> Result<(), Error> {
+        let mut tdata = self.create_response(rdata, status_code, reason)?;
+
+        let prepared = headers
+            .iter()
+            .try_for_each(|(name, value)| tdata.add_header(name, value))
+            .and_then(|_| match body {
+                Some((content_type, body)) => tdata.set_body(content_type, body),
+                None => Ok(()),
+            });
+        if let Err(err) = prepared {
+            unsafe { pj::pjsip_tx_data_dec_ref(tdata.as_mut_ptr()) };
+            return Err(err);
+        }
+
+        self.send_response(rdata, tdata)
+    }
+
+    /** Credentials to answer 401 and 407 challenges with. Requests of an
+     * invite session, the INVITE and in-dialog requests alike, are then
+     * re-sent with credentials after a challenge. */
+    pub fn set_credentials(&mut self, credentials: &[PjSipCredInfo]) -> Result<(), Error> {
+        unsafe { set_credentials(&mut (*self.dialog).auth_sess, credentials) }
+    }
+
     pub fn pool(&self) -> PjPoolRef {
         PjPoolRef::from((unsafe { *self.dialog }).pool)
     }
+
+    /** Attach data for `module` to the dialog, replacing any data it
+     * attached before. The data is released when pjsip destroys the
+     * dialog. */
+    pub fn set_mod_data<D: Any + Send + Sync>(
+        &mut self,
+        module: &PjSipModule,
+        data: D,
+    ) -> Result<(), Error> {
+        unsafe {
+            set_mod_data(
+                std::ptr::addr_of_mut!((*self.dialog).mod_data),
+                (*self.dialog).grp_lock_,
+                (*self.dialog).pool,
+                module,
+                data,
+            )
+        }
+    }
+
+    /** Data attached by `module`, if it is of type `D`. */
+    pub fn mod_data<D: Any + Send + Sync>(&self, module: &PjSipModule) -> Option<Arc<D>> {
+        unsafe {
+            get_mod_data(
+                std::ptr::addr_of!((*self.dialog).mod_data),
+                (*self.dialog).grp_lock_,
+                module,
+            )
+        }
+    }
+
+    pub fn remove_mod_data(&mut self, module: &PjSipModule) {
+        unsafe {
+            remove_mod_data(
+                std::ptr::addr_of_mut!((*self.dialog).mod_data),
+                (*self.dialog).grp_lock_,
+                module,
+            )
+        }
+    }
+}
+
+/* Copy the URI of a From or To header of the dialog */
+fn fromto_name_addr(info: *const pj::pjsip_fromto_hdr) -> Result<PjSipNameAddr, Error> {
+    let printed = PjSipUriRef::from(unsafe { (*info).uri } as *const _)
+        .print(pj::pjsip_uri_context_e_PJSIP_URI_IN_FROMTO_HDR);
+
+    PjSipNameAddr::parse(CString::new(printed)?)
+}
+
+/** Holds the dialog lock, see `PjSipDialog::inc_lock`. Dropping it may
+ * destroy the dialog when no handle, session or transaction holds it. */
+pub struct PjSipDialogLock {
+    dialog: *mut pj::pjsip_dialog,
+}
+
+impl Drop for PjSipDialogLock {
+    fn drop(&mut self) {
+        unsafe { pj::pjsip_dlg_dec_lock(self.dialog) };
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum PjSipDialogState {
+    Null,
+    Established,
+}
+
+impl From<u8> for PjSipDialogState {
+    fn from(value: u8) -> Self {
+        match value as pj::pjsip_dialog_state {
+            pj::pjsip_dialog_state_PJSIP_DIALOG_STATE_ESTABLISHED => Self::Established,
+            _ => Self::Null,
+        }
+    }
+}
+
+impl Display for PjSipDialogState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                PjSipDialogState::Null => "Null",
+                PjSipDialogState::Established => "Established",
+            }
+        )
+    }
+}
+
+impl Debug for PjSipDialogState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{self}[{}]", *self as u8)
+    }
+}
+
+impl Clone for PjSipDialog {
+    fn clone(&self) -> Self {
+        Self::from(self.dialog)
+    }
+}
+
+impl Drop for PjSipDialog {
+    fn drop(&mut self) {
+        unsafe { pj::pjsip_dlg_dec_session(self.dialog, (*self.dialog).ua) };
+    }
+}
+
+/** Take a new handle on a dialog owned by pjsip. */
+impl From<*mut pj::pjsip_dialog> for PjSipDialog {
+    #[allow(clippy::not_unsafe_ptr_arg_deref)]
+    fn from(dialog: *mut pj::pjsip_dialog) -> Self {
+        unsafe { pj::pjsip_dlg_inc_session(dialog, (*dialog).ua) };
+
+        Self { dialog }
+    }
 }
@@ -1,10 +1,17 @@
-use std::{ffi::CStr, sync::Arc};
+use std::{ffi::CStr, os::raw::c_void, sync::Arc, time::Duration};
+
+use parking_lot::Mutex;
 
 use pjproject_sys as pj;
 
 use crate::{
-    Error, PjCachingPool, PjIoqueue, PjSipInvCallback, PjSipModule, PjSipTransportUdp,
-    PjSockaddrInRef, PjStatus, PjTimeVal,
+    create_body, create_hdr_list, pjsip_ua::sip_inv::unregister_inv_callback, register_tpmgr,
+    set_tpmgr_state_cb, tpmgr_transports, unregister_tpmgr, Error, PjCachingPool, PjIoqueue,
+    PjPool, PjSipAuthClientSession, PjSipEvent, PjSipEventBody, PjSipInvCallback, PjSipModule,
+    PjSipResponse, PjSipRxData, PjSipTpSelector, PjSipTransport, PjSipTransportInfo,
+    PjSipTransportLoop, PjSipTransportRef, PjSipTransportState, PjSipTransportTcp,
+    PjSipTransportType, PjSipTransportUdp, PjSipTsxStateSrc, PjSipTxData, PjSipUriRef, PjStatus,
+    PjTimeVal, SockaddrT, SockaddrTRef,
 };
 
 use super::PjSipHostPortRef;
@@ -31,23 +38,120 @@ impl PjSipEndpoint {
             )
         };
 
-        PjStatus::result_for_status(status).map(|_| Self {
+        PjStatus::result_for_status(status)?;
+
+        let endpt = Self {
             pjsip_endpoint,
             caching_pool,
-        })
+        };
+        register_tpmgr(endpt.tpmgr())?;
+
+        Ok(endpt)
+    }
+
+    fn tpmgr(&self) -> *mut pj::pjsip_tpmgr {
+        unsafe { pj::pjsip_endpt_get_tpmgr(self.as_mut_ptr()) }
+    }
+
+    /** Transports of the endpoint: the UDP and loop transports it started
+     * and the TCP and TLS connections currently open. */
+    pub fn transports(&self) -> Vec<PjSipTransportInfo> {
+        tpmgr_transports(self.tpmgr())
+    }
+
+    /** Number of transports pjsip knows of, listeners included. */
+    pub fn transport_count(&self) -> u32 {
+        unsafe { pj::pjsip_tpmgr_get_transport_count(self.tpmgr()) }
+    }
+
+    /** Find the transport messages to `remote` would be sent over, opening
+     * a connection for connection oriented types. */
+    pub fn acquire_transport(
+        &self,
+        transport_type: PjSipTransportType,
+        remote: &SockaddrT,
+        selector: Option<&PjSipTpSelector>,
+    ) -> Result<PjSipTransport, Error> {
+        let remote_len = unsafe { pj::pj_sockaddr_get_len(remote.as_ptr() as *const _) };
+
+        let mut pjsip_transport = std::ptr::null_mut();
+        let status = unsafe {
+            pj::pjsip_tpmgr_acquire_transport(
+                self.tpmgr(),
+                transport_type.into(),
+                remote.as_ptr() as *const _,
+                remote_len as _,
+                selector.map_or(std::ptr::null(), |s| s.as_ptr()),
+                &mut pjsip_transport,
+            )
+        };
+        PjStatus::result_for_status(status)?;
+
+        /* The acquired reference is handed over to the PjSipTransport */
+        let transport = PjSipTransport::from(&PjSipTransportRef::from(pjsip_transport));
+        unsafe { pj::pjsip_transport_dec_ref(pjsip_transport) };
+
+        Ok(transport)
+    }
+
+    /** Called when a connection oriented transport connects, disconnects,
+     * and when any transport is shut down or destroyed. The status tells
+     * why a connection was lost. */
+    pub fn set_transport_state_cb<F>(&self, cb: F)
+    where
+        F: Fn(&PjSipTransportRef, PjSipTransportState, PjStatus) + Send + Sync + 'static,
+    {
+        set_tpmgr_state_cb(self.tpmgr(), Some(Arc::new(cb)));
     }
 
     pub fn as_mut_ptr(&self) -> *mut pj::pjsip_endpoint {
         self.pjsip_endpoint
     }
 
-    pub fn udp_transport_start(
+    /** Start a UDP transport, `local` is an IPv4 or IPv6 address, e.g. a
+     * `&PjSockaddrInRef`, `&PjSockaddrIn6Ref` or `&SockaddrT`. */
+    pub fn udp_transport_start<'a, L: Into<SockaddrTRef<'a>>>(
         &self,
-        local: &PjSockaddrInRef,
+        local: L,
         a_name: Option<&PjSipHostPortRef>,
         async_cnt: u32,
     ) -> Result<PjSipTransportUdp, Error> {
-        PjSipTransportUdp::new_from_endpoint(self, local, a_name, async_cnt)
+        PjSipTransportUdp::new_from_endpoint(self, &local.into(), a_name, async_cnt)
+    }
+
+    /** Start an in-process loop transport, see `PjSipTransportLoop`. */
+    pub fn loop_transport_start(&self) -> Result<PjSipTransportLoop, Error> {
+        PjSipTransportLoop::new_from_endpoint(self)
+    }
+
+    /** Start a TCP listener, see `PjSipTransportTcp::new_from_endpoint`. */
+    pub fn tcp_transport_start(
+        &self,
+        local: &SockaddrT,
+        a_name: Option<&PjSipHostPortRef>,
+        async_cnt: u32,
+    ) -> Result<PjSipTransportTcp, Error> {
+        PjSipTransportTcp::new_from_endpoint(self, local, a_name, async_cnt)
+    }
+
+    /** How often a CRLF keep-alive is sent on idle TCP connections,
+     * `Duration::ZERO` disables it. pjsip keeps it in its global
+     * configuration, so it applies to every TCP connection of the
+     * process. */
+    pub fn set_tcp_keep_alive_interval(&self, interval: Duration) {
+        unsafe { (*pj::pjsip_cfg()).tcp.keep_alive_interval = interval.as_secs() as _ };
+    }
+
+    /** Start a TLS listener, see `PjSipTransportTls::new_from_endpoint`. */
+    #[cfg(feature = "tls")]
+    pub fn tls_transport_start(
+        &self,
+        setting: &crate::PjSipTlsSetting,
+        local: Option<&SockaddrT>,
+        a_name: Option<&PjSipHostPortRef>,
+        async_cnt: u32,
+    ) -> Result<crate::PjSipTransportTls, Error> {
+        crate::PjSipTransportTls::new_from_endpoint(self, setting, local, a_name, async_cnt)
     }
 
     pub fn init_tsx_layer_module(&self) -> Result<(), Error> {
@@ -68,10 +172,13 @@ impl PjSipEndpoint {
         PjStatus::result_for_status(status)
     }
 
-    pub fn init_inv_usage<T>(&self, inv_cb: &PjSipInvCallback<T>) -> Result<(), Error> {
-        let status = unsafe { pj::pjsip_inv_usage_init(self.as_mut_ptr(), inv_cb.as_ptr()) };
+    /** Initialize the invite usage with `inv_cb`. The callbacks stay alive,
+     * and may hold their own state, until this endpoint is dropped. */
+    pub fn init_inv_usage(&self, inv_cb: PjSipInvCallback) -> Result<(), Error> {
+        let pjsip_inv_callback = inv_cb.register(self);
+        let status = unsafe { pj::pjsip_inv_usage_init(self.as_mut_ptr(), &pjsip_inv_callback) };
 
-        PjStatus::result_for_status(status)
+        PjStatus::result_for_status(status).inspect_err(|_| unregister_inv_callback(self))
     }
 
     pub fn register_module(endpt: Arc<Self>, module: &mut PjSipModule) -> Result<(), Error> {
@@ -90,6 +197,285 @@ impl PjSipEndpoint {
         PjStatus::result_for_status(status)
     }
 
+    /** Create a response to `rdata`, `reason` defaults to the standard
+     * text for `status_code`. */
+    pub fn create_response<S: AsRef<CStr>>(
+        &self,
+        rdata: &PjSipRxData,
+        status_code: u16,
+        reason: Option<S>,
+    ) -> Result<PjSipTxData, Error> {
+        let reason = reason.map(|r| unsafe { pj::pj_str(r.as_ref().as_ptr() as *mut _) });
+
+        let mut pjsip_tx_data = std::ptr::null_mut();
+        let status = unsafe {
+            pj::pjsip_endpt_create_response(
+                self.as_mut_ptr(),
+                rdata.as_ptr(),
+                status_code as _,
+                reason.as_ref().map_or(std::ptr::null(), |r| r as *const _),
+                &mut pjsip_tx_data,
+            )
+        };
+        PjStatus::result_for_status(status)?;
+
+        if pjsip_tx_data.is_null() {
+            return Err(Error::Validation("Got null creating PjSipTxData".into()));
+        }
+
+        Ok(PjSipTxData::from(pjsip_tx_data))
+    }
+
+    /** Send a response statelessly to the address the request came from,
+     * following the Via rules of RFC 3261. */
+    pub fn send_response(
+        &self,
+        rdata: &mut PjSipRxData,
+        mut tdata: PjSipTxData,
+    ) -> Result<(), Error> {
+        let status = unsafe {
+            pj::pjsip_endpt_send_response2(
+                self.as_mut_ptr(),
+                rdata.as_mut_ptr(),
+                tdata.as_mut_ptr(),
+                std::ptr::null_mut(),
+                None,
+            )
+        };
+        /* pjsip only takes the reference when sending starts */
+        if status != pj::pj_constants__PJ_SUCCESS as _ {
+            unsafe { pj::pjsip_tx_data_dec_ref(tdata.as_mut_ptr()) };
+        }
+
+        PjStatus::result_for_status(status)
+    }
+
+    /** Answer a request without creating a transaction, e.g. to reject an
+     * unsupported method with 405 and an `Allow` header. `body` is a
+     * content type, `type/subtype`, and the body data. */
+    pub fn respond_stateless<S: AsRef<CStr>>(
+        &self,
+        rdata: &mut PjSipRxData,
+        status_code: u16,
+        reason: Option<S>,
+        headers: &[(&CStr, &CStr)],
+        body: Option<(&CStr, &[u8])>,
+    ) -> Result<(), Error> {
+        let reason = reason.map(|r| unsafe { pj::pj_str(r.as_ref().as_ptr() as *mut _) });
+
+        /* pjsip copies the headers and body into the response */
+        let mut pool = PjPool::default_with_name(c"respond");
+        let mut hdr_list = unsafe { std::mem::zeroed::<pj::pjsip_hdr>() };
+        create_hdr_list(pool.as_mut_ptr(), headers, &mut hdr_list)?;
+        let body = match body {
+            Some((content_type, body)) => create_body(pool.as_mut_ptr(), content_type, body)?,
+            None => std::ptr::null_mut(),
+        };
+
+        let status = unsafe {
+            pj::pjsip_endpt_respond_stateless(
+                self.as_mut_ptr(),
+                rdata.as_mut_ptr(),
+                status_code as _,
+                reason.as_ref().map_or(std::ptr::null(), |r| r as *const _),
+                if headers.is_empty() {
+                    std::ptr::null()
+                } else {
+                    &hdr_list
+                },
+                body,
+            )
+        };
+
+        PjStatus::result_for_status(status)
+    }
+
+    /** Create a copy of the request in `rdata` to forward it statelessly,
+     * to `uri` or else to its Request-URI. A Via with a branch derived
+     * from the request's is added, so retransmissions get the same one. */
+    pub fn create_request_fwd(
+        &self,
+        rdata: &mut PjSipRxData,
+        uri: Option<&PjSipUriRef>,
+    ) -> Result<PjSipTxData, Error> {
+        let mut pjsip_tx_data = std::ptr::null_mut();
+        let status = unsafe {
+            pj::pjsip_endpt_create_request_fwd(
+                self.as_mut_ptr(),
+                rdata.as_mut_ptr(),
+                uri.map_or(std::ptr::null(), |uri| uri.as_ptr()),
+                std::ptr::null(),
+                0,
+                &mut pjsip_tx_data,
+            )
+        };
+        PjStatus::result_for_status(status)?;
+
+        if pjsip_tx_data.is_null() {
+            return Err(Error::Validation("Got null creating PjSipTxData".into()));
+        }
+
+        Ok(PjSipTxData::from(pjsip_tx_data))
+    }
+
+    /** Send a request without a transaction, the destination is resolved
+     * from the Route headers or the Request-URI. */
+    pub fn send_request_stateless(&self, mut tdata: PjSipTxData) -> Result<(), Error> {
+        let status = unsafe {
+            pj::pjsip_endpt_send_request_stateless(
+                self.as_mut_ptr(),
+                tdata.as_mut_ptr(),
+                std::ptr::null_mut(),
+                None,
+            )
+        };
+        /* pjsip only takes the reference when sending starts */
+        if status != pj::pj_constants__PJ_SUCCESS as _ {
+            unsafe { pj::pjsip_tx_data_dec_ref(tdata.as_mut_ptr()) };
+        }
+
+        PjStatus::result_for_status(status)
+    }
+
+    /** Forward the request in `rdata` statelessly, see
+     * `create_request_fwd`. */
+    pub fn forward_request(
+        &self,
+        rdata: &mut PjSipRxData,
+        uri: Option<&PjSipUriRef>,
+    ) -> Result<(), Error> {
+        let tdata = self.create_request_fwd(rdata, uri)?;
+
+        self.send_request_stateless(tdata)
+    }
+
+    /** Create an out of dialog request, e.g. OPTIONS or MESSAGE. `target`
+     * is the Request-URI, `from`, `to` and `contact` are header values. A
+     * new Call-ID and CSeq are generated. */
+    pub fn create_request<M, T, F, O>(
+        &self,
+        method: M,
+        target: T,
+        from: F,
+        to: O,
+        contact: Option<&CStr>,
+    ) -> Result<PjSipTxData, Error>
+    where
+        M: AsRef<CStr>,
+        T: AsRef<CStr>,
+        F: AsRef<CStr>,
+        O: AsRef<CStr>,
+    {
+        let as_pj_str = |s: &CStr| unsafe { pj::pj_str(s.as_ptr() as *mut _) };
+
+        let mut pjsip_method = unsafe { std::mem::zeroed::<pj::pjsip_method>() };
+        let mut method = as_pj_str(method.as_ref());
+        unsafe { pj::pjsip_method_init_np(&mut pjsip_method, &mut method) };
+
+        let contact = contact.map(as_pj_str);
+
+        let mut pjsip_tx_data = std::ptr::null_mut();
+        let status = unsafe {
+            pj::pjsip_endpt_create_request(
+                self.as_mut_ptr(),
+                &pjsip_method,
+                &as_pj_str(target.as_ref()),
+                &as_pj_str(from.as_ref()),
+                &as_pj_str(to.as_ref()),
+                contact.as_ref().map_or(std::ptr::null(), |c| c as *const _),
+                std::ptr::null(),
+                -1,
+                std::ptr::null(),
+                &mut pjsip_tx_data,
+            )
+        };
+        PjStatus::result_for_status(status)?;
+
+        if pjsip_tx_data.is_null() {
+            return Err(Error::Validation("Got null creating PjSipTxData".into()));
+        }
+
+        Ok(PjSipTxData::from(pjsip_tx_data))
+    }
+
+    /** Send a request in its own client transaction and call `cb` once with
+     * the final response, or with the 408/503 pjsip reports on timeout or
+     * transport failure. `timeout` overrides the transaction timeout. The
+     * callback runs on the thread polling the endpoint, use a channel to
+     * hand the result to async code.
+     *
+     * Either an error is returned or the callback is called, never both. */
+    pub fn send_request<F>(
+        &self,
+        mut tdata: PjSipTxData,
+        timeout: Option<Duration>,
+        cb: F,
+    ) -> Result<(), Error>
+    where
+        F: FnOnce(PjSipResponse<'_>) + Send + 'static,
+    {
+        let pending: PendingRequest = Arc::new(Mutex::new(Some(Box::new(cb))));
+        let token = Arc::into_raw(pending.clone()) as *mut c_void;
+
+        let timeout = timeout.map_or(-1, |t| t.as_millis().min(i32::MAX as _) as _);
+        let status = unsafe {
+            pj::pjsip_endpt_send_request(
+                self.as_mut_ptr(),
+                tdata.as_mut_ptr(),
+                timeout,
+                token,
+                Some(on_send_request_complete),
+            )
+        };
+        if status == pj::pj_constants__PJ_SUCCESS as _ {
+            return Ok(());
+        }
+
+        /* A failed send terminates the transaction, which already reported
+         * the failure to the callback. Otherwise the transaction was never
+         * created and pjsip won't call back. */
+        if pending.lock().take().is_none() {
+            return Ok(());
+        }
+        drop(unsafe { Arc::from_raw(token as *const Mutex<Option<OnRequestComplete>>) });
+
+        PjStatus::result_for_status(status)
+    }
+
+    /** Same as `send_request`, answering 401 and 407 challenges with the
+     * credentials of `auth` and re-sending the request. The callback gets
+     * the response to the last request sent, the challenge itself if it
+     * could not be answered. */
+    pub fn send_request_with_auth<F>(
+        &self,
+        mut tdata: PjSipTxData,
+        auth: &Arc<PjSipAuthClientSession>,
+        timeout: Option<Duration>,
+        cb: F,
+    ) -> Result<(), Error>
+    where
+        F: FnOnce(PjSipResponse<'_>) + Send + 'static,
+    {
+        if let Err(err) = auth.init_req(&mut tdata) {
+            unsafe { pj::pjsip_tx_data_dec_ref(tdata.as_mut_ptr()) };
+            return Err(err);
+        }
+
+        let request = AuthRequest {
+            endpt: self.as_mut_ptr(),
+            auth: auth.clone(),
+            tdata: tdata.as_mut_ptr(),
+            timeout: timeout.map_or(-1, |t| t.as_millis().min(i32::MAX as _) as _),
+            cb: Some(Box::new(cb)),
+        };
+
+        match send_auth_request(request) {
+            /* Without the request back the callback was already called */
+            Ok(()) | Err((_, None)) => Ok(()),
+            Err((status, Some(_))) => PjStatus::result_for_status(status),
+        }
+    }
+
     pub fn get_ioqueue<'a>(&'a self) -> PjIoqueue<'a> {
         let ioqueue = unsafe { pj::pjsip_endpt_get_ioqueue(self.as_mut_ptr()) };
 
@@ -103,10 +489,129 @@ impl PjSipEndpoint {
     }
 }
 
+type OnRequestComplete = Box<dyn FnOnce(PjSipResponse<'_>) + Send>;
+type PendingRequest = Arc<Mutex<Option<OnRequestComplete>>>;
+
+unsafe extern "C" fn on_send_request_complete(token: *mut c_void, event: *mut pj::pjsip_event) {
+    let pending = Arc::from_raw(token as *const Mutex<Option<OnRequestComplete>>);
+    let Some(cb) = pending.lock().take() else {
+        return;
+    };
+
+    let event = PjSipEvent::from(event);
+    if let PjSipEventBody::TsxState { tsx, src, .. } = event.body() {
+        let rdata = match src {
+            PjSipTsxStateSrc::RxMsg(rdata) => Some(rdata),
+            _ => None,
+        };
+
+        cb(PjSipResponse {
+            status_code: tsx.status_code(),
+            status_text: tsx.status_text(),
+            rdata,
+        });
+    }
+}
+
+/* A request sent with send_request_with_auth. It holds a reference on the
+ * request, to re-send it with credentials after a challenge. */
+struct AuthRequest {
+    endpt: *mut pj::pjsip_endpoint,
+    auth: Arc<PjSipAuthClientSession>,
+    tdata: *mut pj::pjsip_tx_data,
+    timeout: i32,
+    cb: Option<OnRequestComplete>,
+}
+
+unsafe impl Send for AuthRequest {}
+
+impl Drop for AuthRequest {
+    fn drop(&mut self) {
+        unsafe { pj::pjsip_tx_data_dec_ref(self.tdata) };
+    }
+}
+
+type PendingAuthRequest = Arc<Mutex<Option<AuthRequest>>>;
+
+/* Send the request, giving it back with the status if pjsip won't call
+ * back for it */
+fn send_auth_request(request: AuthRequest) -> Result<(), (pj::pj_status_t, Option<AuthRequest>)> {
+    let (endpt, tdata, timeout) = (request.endpt, request.tdata, request.timeout);
+
+    let pending: PendingAuthRequest = Arc::new(Mutex::new(Some(request)));
+    let token = Arc::into_raw(pending.clone()) as *mut c_void;
+
+    /* Sending takes a reference, the request keeps its own */
+    let status = unsafe {
+        pj::pjsip_tx_data_add_ref(tdata);
+        pj::pjsip_endpt_send_request(endpt, tdata, timeout, token, Some(on_auth_request_complete))
+    };
+    if status == pj::pj_constants__PJ_SUCCESS as _ {
+        return Ok(());
+    }
+
+    /* As in send_request, the callback may already have run */
+    let request = pending.lock().take();
+    if request.is_some() {
+        drop(unsafe { Arc::from_raw(token as *const Mutex<Option<AuthRequest>>) });
+    }
+
+    Err((status, request))
+}
+
+unsafe extern "C" fn on_auth_request_complete(token: *mut c_void, event: *mut pj::pjsip_event) {
+    let pending = Arc::from_raw(token as *const Mutex<Option<AuthRequest>>);
+    let Some(mut request) = pending.lock().take() else {
+        return;
+    };
+
+    let event = PjSipEvent::from(event);
+    let PjSipEventBody::TsxState { tsx, src, .. } = event.body() else {
+        return;
+    };
+    let rdata = match src {
+        PjSipTsxStateSrc::RxMsg(rdata) => Some(rdata),
+        _ => None,
+    };
+
+    let status_code = tsx.status_code();
+    if let (401 | 407, Some(rdata)) = (status_code, rdata.as_ref()) {
+        let mut old = PjSipTxData::from(request.tdata);
+        match request.auth.reinit_req(rdata, &mut old) {
+            Ok(mut tdata) => {
+                let retry = AuthRequest {
+                    endpt: request.endpt,
+                    auth: request.auth.clone(),
+                    tdata: tdata.as_mut_ptr(),
+                    timeout: request.timeout,
+                    cb: request.cb.take(),
+                };
+                match send_auth_request(retry) {
+                    Ok(()) | Err((_, None)) => return,
+                    /* Report the challenge we could not answer */
+                    Err((_, Some(mut retry))) => request.cb = retry.cb.take(),
+                }
+            }
+            Err(err) => tracing::error!("Failed to answer {status_code} challenge: {err}"),
+        }
+    }
+
+    if let Some(cb) = request.cb.take() {
+        cb(PjSipResponse {
+            status_code,
+            status_text: tsx.status_text(),
+            rdata,
+        });
+    }
+}
+
 impl Drop for PjSipEndpoint {
     fn drop(&mut self) {
+        let tpmgr = self.tpmgr();
         unsafe {
             pj::pjsip_endpt_destroy(self.as_mut_ptr());
         };
+        unregister_tpmgr(tpmgr);
+        unregister_inv_callback(self);
     }
 }
@@ -0,0 +1,11 @@
+use pjproject_sys as pj;
+
+pub const PJSIP_EFAILEDCREDENTIAL: i32 = pj::PJSIP_EFAILEDCREDENTIAL as i32;
+pub const PJSIP_ENOCREDENTIAL: i32 = pj::PJSIP_ENOCREDENTIAL as i32;
+pub const PJSIP_EAUTHNOAUTH: i32 = pj::PJSIP_EAUTHNOAUTH as i32;
+pub const PJSIP_EAUTHACCNOTFOUND: i32 = pj::PJSIP_EAUTHACCNOTFOUND as i32;
+pub const PJSIP_EAUTHACCDISABLED: i32 = pj::PJSIP_EAUTHACCDISABLED as i32;
+pub const PJSIP_EAUTHINVALIDREALM: i32 = pj::PJSIP_EAUTHINVALIDREALM as i32;
+pub const PJSIP_EAUTHINVALIDDIGEST: i32 = pj::PJSIP_EAUTHINVALIDDIGEST as i32;
+pub const PJSIP_EAUTHSTALECOUNT: i32 = pj::PJSIP_EAUTHSTALECOUNT as i32;
+pub const PJSIP_EAUTHINNONCE: i32 = pj::PJSIP_EAUTHINNONCE as i32;
@@ -1,12 +1,121 @@
+use std::os::raw::c_void;
+
 use pjproject_sys as pj;
 
-#[allow(dead_code)]
+use crate::{PjSipRxData, PjSipTransaction, PjSipTsxState, PjSipTxData, PjStatus};
+
 pub struct PjSipEvent {
     pjsip_event: *mut pj::pjsip_event,
 }
 
+impl PjSipEvent {
+    pub fn as_ptr(&self) -> *const pj::pjsip_event {
+        self.pjsip_event
+    }
+
+    pub fn as_mut_ptr(&mut self) -> *mut pj::pjsip_event {
+        self.pjsip_event
+    }
+
+    pub fn as_ref(&self) -> &pj::pjsip_event {
+        unsafe { &*self.pjsip_event }
+    }
+
+    /** Decode the event. The views it hands out are only valid for the
+     * duration of the callback the event was passed to. */
+    pub fn body(&self) -> PjSipEventBody<'_> {
+        let evt = self.as_ref();
+        unsafe {
+            match evt.type_ {
+                pj::pjsip_event_id_e_PJSIP_EVENT_TIMER => PjSipEventBody::Timer,
+                pj::pjsip_event_id_e_PJSIP_EVENT_TX_MSG => {
+                    PjSipEventBody::TxMsg(PjSipTxData::from(evt.body.tx_msg.tdata))
+                }
+                pj::pjsip_event_id_e_PJSIP_EVENT_RX_MSG => {
+                    PjSipEventBody::RxMsg(PjSipRxData::from(evt.body.rx_msg.rdata))
+                }
+                pj::pjsip_event_id_e_PJSIP_EVENT_TRANSPORT_ERROR => {
+                    let tx_error = evt.body.tx_error;
+                    PjSipEventBody::TransportError {
+                        tdata: PjSipTxData::from(tx_error.tdata),
+                        tsx: (!tx_error.tsx.is_null())
+                            .then(|| PjSipTransaction::from(tx_error.tsx)),
+                    }
+                }
+                pj::pjsip_event_id_e_PJSIP_EVENT_TSX_STATE => {
+                    let tsx_state = evt.body.tsx_state;
+                    let src = match tsx_state.type_ {
+                        pj::pjsip_event_id_e_PJSIP_EVENT_TIMER => PjSipTsxStateSrc::Timer,
+                        pj::pjsip_event_id_e_PJSIP_EVENT_RX_MSG => {
+                            PjSipTsxStateSrc::RxMsg(PjSipRxData::from(tsx_state.src.rdata))
+                        }
+                        pj::pjsip_event_id_e_PJSIP_EVENT_TX_MSG => {
+                            PjSipTsxStateSrc::TxMsg(PjSipTxData::from(tsx_state.src.tdata))
+                        }
+                        pj::pjsip_event_id_e_PJSIP_EVENT_TRANSPORT_ERROR => {
+                            PjSipTsxStateSrc::TransportError(PjStatus::new(tsx_state.src.status))
+                        }
+                        pj::pjsip_event_id_e_PJSIP_EVENT_USER => {
+                            PjSipTsxStateSrc::User(tsx_state.src.data)
+                        }
+                        _ => PjSipTsxStateSrc::Unknown,
+                    };
+
+                    PjSipEventBody::TsxState {
+                        tsx: PjSipTransaction::from(tsx_state.tsx),
+                        prev_state: (tsx_state.prev_state as u8).into(),
+                        src,
+                    }
+                }
+                pj::pjsip_event_id_e_PJSIP_EVENT_USER => {
+                    let user = evt.body.user;
+                    PjSipEventBody::User([user.user1, user.user2, user.user3, user.user4])
+                }
+                _ => PjSipEventBody::Unknown,
+            }
+        }
+    }
+}
+
 impl From<*mut pj::pjsip_event> for PjSipEvent {
     fn from(value: *mut pj::pjsip_event) -> Self {
         Self { pjsip_event: value }
     }
 }
+
+/** What an event is about, see `PjSipEvent::body`. */
+pub enum PjSipEventBody<'a> {
+    Unknown,
+    /** A timer fired. */
+    Timer,
+    /** A message is about to be sent. */
+    TxMsg(PjSipTxData),
+    /** A message was received. */
+    RxMsg(PjSipRxData<'a>),
+    /** Sending a message failed. */
+    TransportError {
+        tdata: PjSipTxData,
+        tsx: Option<PjSipTransaction>,
+    },
+    /** A transaction changed state. The transaction holds the new state,
+     * `src` tells what triggered the change. */
+    TsxState {
+        tsx: PjSipTransaction,
+        prev_state: PjSipTsxState,
+        src: PjSipTsxStateSrc<'a>,
+    },
+    /** Event raised by the application with up to four user pointers. */
+    User([*mut c_void; 4]),
+}
+
+/** What made a transaction change state. */
+pub enum PjSipTsxStateSrc<'a> {
+    Unknown,
+    Timer,
+    /** A request or response was received, e.g. the final response that
+     * ended a call. */
+    RxMsg(PjSipRxData<'a>),
+    TxMsg(PjSipTxData),
+    TransportError(PjStatus),
+    User(*mut c_void),
+}
@@ -1,17 +1,227 @@
 use std::{
+    any::Any,
     ffi::{CStr, CString},
-    sync::Weak,
+    fmt::{Debug, Display},
+    os::raw::c_void,
+    sync::{Arc, Weak},
 };
 
+use parking_lot::RwLock;
 use pjproject_sys as pj;
 
 use crate::{Error, PjStatus};
 
-use super::PjSipEndpoint;
+use super::{PjSipEndpoint, PjSipEvent, PjSipRxData, PjSipTransaction, PjSipTxData};
+
+/** What a module did with an incoming message. */
+#[derive(Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum PjSipModuleAction {
+    /** The message was processed, it is not given to lower priority
+     * modules. */
+    Handled,
+    /** Give the message to the next module. */
+    PassOn,
+}
+
+impl From<PjSipModuleAction> for pj::pj_bool_t {
+    fn from(value: PjSipModuleAction) -> Self {
+        match value {
+            PjSipModuleAction::Handled => pj::pj_constants__PJ_TRUE as _,
+            PjSipModuleAction::PassOn => pj::pj_constants__PJ_FALSE as _,
+        }
+    }
+}
+
+impl Display for PjSipModuleAction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                PjSipModuleAction::Handled => "Handled",
+                PjSipModuleAction::PassOn => "PassOn",
+            }
+        )
+    }
+}
+
+impl Debug for PjSipModuleAction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{self}[{}]", *self as u8)
+    }
+}
+
+/** Where a module sits in the stack, modules with a lower value see
+ * incoming messages first and outgoing messages last. */
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum PjSipModulePriority {
+    TransportLayer,
+    TsxLayer,
+    UaProxyLayer,
+    DialogUsage,
+    Application,
+    Custom(i32),
+}
+
+impl From<PjSipModulePriority> for i32 {
+    fn from(value: PjSipModulePriority) -> Self {
+        (match value {
+            PjSipModulePriority::TransportLayer => {
+                pj::pjsip_module_priority_PJSIP_MOD_PRIORITY_TRANSPORT_LAYER
+            }
+            PjSipModulePriority::TsxLayer => pj::pjsip_module_priority_PJSIP_MOD_PRIORITY_TSX_LAYER,
+            PjSipModulePriority::UaProxyLayer => {
+                pj::pjsip_module_priority_PJSIP_MOD_PRIORITY_UA_PROXY_LAYER
+            }
+            PjSipModulePriority::DialogUsage => {
+                pj::pjsip_module_priority_PJSIP_MOD_PRIORITY_DIALOG_USAGE
+            }
+            PjSipModulePriority::Application => {
+                pj::pjsip_module_priority_PJSIP_MOD_PRIORITY_APPLICATION
+            }
+            PjSipModulePriority::Custom(priority) => return priority,
+        }) as _
+    }
+}
+
+type OnLifecycle = Arc<dyn Fn() -> Result<(), Error> + Send + Sync>;
+type OnRxMsg = Arc<dyn Fn(&mut PjSipRxData) -> PjSipModuleAction + Send + Sync>;
+type OnTxMsg = Arc<dyn Fn(&mut PjSipTxData) -> Result<(), Error> + Send + Sync>;
+type OnTsxState = Arc<dyn Fn(&mut PjSipTransaction, &mut PjSipEvent) + Send + Sync>;
+
+#[derive(Default)]
+struct PjSipModuleCallbacks {
+    load: Option<OnLifecycle>,
+    start: Option<OnLifecycle>,
+    stop: Option<OnLifecycle>,
+    unload: Option<OnLifecycle>,
+    on_rx_request: Option<OnRxMsg>,
+    on_rx_response: Option<OnRxMsg>,
+    on_tx_request: Option<OnTxMsg>,
+    on_tx_response: Option<OnTxMsg>,
+    on_tsx_state: Option<OnTsxState>,
+}
+
+/* pjsip does not hand the module to its callbacks, so every PjSipModule
+ * takes one of these slots and is given the trampolines for that slot. */
+#[allow(clippy::declare_interior_mutable_const)]
+const MODULE_SLOT_INIT: RwLock<Option<PjSipModuleCallbacks>> = parking_lot::const_rwlock(None);
+static MODULE_SLOTS: [RwLock<Option<PjSipModuleCallbacks>>; MAX_MODULE] =
+    [MODULE_SLOT_INIT; MAX_MODULE];
+
+/* Clone the callback out of the slot so the slot lock isn't held while
+ * the callback runs */
+fn slot_callback<T: Clone>(
+    slot: usize,
+    f: impl FnOnce(&PjSipModuleCallbacks) -> Option<&T>,
+) -> Option<T> {
+    MODULE_SLOTS[slot].read().as_ref().and_then(f).cloned()
+}
+
+macro_rules! module_trampolines {
+    ($f:ident) => {
+        [
+            $f::<0>, $f::<1>, $f::<2>, $f::<3>, $f::<4>, $f::<5>, $f::<6>, $f::<7>, $f::<8>,
+            $f::<9>, $f::<10>, $f::<11>, $f::<12>, $f::<13>, $f::<14>, $f::<15>, $f::<16>,
+            $f::<17>, $f::<18>, $f::<19>, $f::<20>, $f::<21>, $f::<22>, $f::<23>, $f::<24>,
+            $f::<25>, $f::<26>, $f::<27>, $f::<28>, $f::<29>, $f::<30>, $f::<31>,
+        ]
+    };
+}
+
+type LoadTrampoline = unsafe extern "C" fn(*mut pj::pjsip_endpoint) -> pj::pj_status_t;
+type LifecycleTrampoline = unsafe extern "C" fn() -> pj::pj_status_t;
+type RxTrampoline = unsafe extern "C" fn(*mut pj::pjsip_rx_data) -> pj::pj_bool_t;
+type TxTrampoline = unsafe extern "C" fn(*mut pj::pjsip_tx_data) -> pj::pj_status_t;
+type TsxStateTrampoline = unsafe extern "C" fn(*mut pj::pjsip_transaction, *mut pj::pjsip_event);
+
+const MAX_MODULE: usize = pj::PJSIP_MAX_MODULE as usize;
+
+static LOAD: [LoadTrampoline; MAX_MODULE] = module_trampolines!(load);
+static START: [LifecycleTrampoline; MAX_MODULE] = module_trampolines!(start);
+static STOP: [LifecycleTrampoline; MAX_MODULE] = module_trampolines!(stop);
+static UNLOAD: [LifecycleTrampoline; MAX_MODULE] = module_trampolines!(unload);
+static ON_RX_REQUEST: [RxTrampoline; MAX_MODULE] = module_trampolines!(on_rx_request);
+static ON_RX_RESPONSE: [RxTrampoline; MAX_MODULE] = module_trampolines!(on_rx_response);
+static ON_TX_REQUEST: [TxTrampoline; MAX_MODULE] = module_trampolines!(on_tx_request);
+static ON_TX_RESPONSE: [TxTrampoline; MAX_MODULE] = module_trampolines!(on_tx_response);
+static ON_TSX_STATE: [TsxStateTrampoline; MAX_MODULE] = module_trampolines!(on_tsx_state);
+
+fn lifecycle_status(cb: Option<OnLifecycle>) -> pj::pj_status_t {
+    match cb.map(|cb| cb()) {
+        Some(Err(err)) => err.status(),
+        _ => pj::pj_constants__PJ_SUCCESS as _,
+    }
+}
+
+unsafe extern "C" fn load<const N: usize>(_endpt: *mut pj::pjsip_endpoint) -> pj::pj_status_t {
+    lifecycle_status(slot_callback(N, |c| c.load.as_ref()))
+}
+
+unsafe extern "C" fn start<const N: usize>() -> pj::pj_status_t {
+    lifecycle_status(slot_callback(N, |c| c.start.as_ref()))
+}
+
+unsafe extern "C" fn stop<const N: usize>() -> pj::pj_status_t {
+    lifecycle_status(slot_callback(N, |c| c.stop.as_ref()))
+}
+
+unsafe extern "C" fn unload<const N: usize>() -> pj::pj_status_t {
+    lifecycle_status(slot_callback(N, |c| c.unload.as_ref()))
+}
+
+fn rx_action(cb: Option<OnRxMsg>, rdata: *mut pj::pjsip_rx_data) -> pj::pj_bool_t {
+    cb.map(|cb| cb(&mut PjSipRxData::from(rdata)))
+        .unwrap_or(PjSipModuleAction::PassOn)
+        .into()
+}
+
+unsafe extern "C" fn on_rx_request<const N: usize>(rdata: *mut pj::pjsip_rx_data) -> pj::pj_bool_t {
+    rx_action(slot_callback(N, |c| c.on_rx_request.as_ref()), rdata)
+}
+
+unsafe extern "C" fn on_rx_response<const N: usize>(
+    rdata: *mut pj::pjsip_rx_data,
+) -> pj::pj_bool_t {
+    rx_action(slot_callback(N, |c| c.on_rx_response.as_ref()), rdata)
+}
+
+fn tx_status(cb: Option<OnTxMsg>, tdata: *mut pj::pjsip_tx_data) -> pj::pj_status_t {
+    match cb.map(|cb| cb(&mut PjSipTxData::from(tdata))) {
+        Some(Err(err)) => err.status(),
+        _ => pj::pj_constants__PJ_SUCCESS as _,
+    }
+}
+
+unsafe extern "C" fn on_tx_request<const N: usize>(
+    tdata: *mut pj::pjsip_tx_data,
+) -> pj::pj_status_t {
+    tx_status(slot_callback(N, |c| c.on_tx_request.as_ref()), tdata)
+}
+
+unsafe extern "C" fn on_tx_response<const N: usize>(
+    tdata: *mut pj::pjsip_tx_data,
+) -> pj::pj_status_t {
+    tx_status(slot_callback(N, |c| c.on_tx_response.as_ref()), tdata)
+}
+
+unsafe extern "C" fn on_tsx_state<const N: usize>(
+    tsx: *mut pj::pjsip_transaction,
+    event: *mut pj::pjsip_event,
+) {
+    if let Some(cb) = slot_callback(N, |c| c.on_tsx_state.as_ref()) {
+        cb(
+            &mut PjSipTransaction::from(tsx),
+            &mut PjSipEvent::from(event),
+        );
+    }
+}
 
 pub struct PjSipModule {
     pjsip_module: PjSipModuleRef,
     sip_endpt: Weak<PjSipEndpoint>,
+    slot: usize,
 }
 
 unsafe impl Send for PjSipModule {}
@@ -23,12 +233,142 @@ impl PjSipModule {
         module.name = unsafe { pj::pj_str(name.as_ref().to_owned().into_raw()) };
         module.id = -1;
 
+        let slot = MODULE_SLOTS
+            .iter()
+            .position(|slot| {
+                let mut slot = slot.write();
+                if slot.is_some() {
+                    return false;
+                }
+                slot.replace(PjSipModuleCallbacks::default());
+
+                true
+            })
+            .ok_or_else(|| Error::Validation("No free pjsip module slots".into()))?;
+
         Ok(Self {
             pjsip_module: Box::into_raw(module).into(),
             sip_endpt: Weak::new(),
+            slot,
         })
     }
 
+    fn set_callbacks(&mut self, f: impl FnOnce(&mut PjSipModuleCallbacks)) {
+        if let Some(callbacks) = MODULE_SLOTS[self.slot].write().as_mut() {
+            f(callbacks);
+        }
+    }
+
+    /** Set the module priority, it has no effect once the module is
+     * registered. Defaults to 0, the highest priority. */
+    pub fn with_priority<P: Into<i32>>(&mut self, priority: P) -> &mut Self {
+        unsafe { (*self.as_mut_ptr()).priority = priority.into() };
+
+        self
+    }
+
+    /** Called when the module is registered with the endpoint. An error
+     * aborts the registration. */
+    pub fn with_load<F>(&mut self, cb: F) -> &mut Self
+    where
+        F: Fn() -> Result<(), Error> + Send + Sync + 'static,
+    {
+        self.set_callbacks(|c| c.load = Some(Arc::new(cb)));
+        unsafe { (*self.as_mut_ptr()).load = Some(LOAD[self.slot]) };
+
+        self
+    }
+
+    /** Called after `load`, or when the endpoint starts. */
+    pub fn with_start<F>(&mut self, cb: F) -> &mut Self
+    where
+        F: Fn() -> Result<(), Error> + Send + Sync + 'static,
+    {
+        self.set_callbacks(|c| c.start = Some(Arc::new(cb)));
+        unsafe { (*self.as_mut_ptr()).start = Some(START[self.slot]) };
+
+        self
+    }
+
+    /** Called when the module is unregistered, before `unload`. */
+    pub fn with_stop<F>(&mut self, cb: F) -> &mut Self
+    where
+        F: Fn() -> Result<(), Error> + Send + Sync + 'static,
+    {
+        self.set_callbacks(|c| c.stop = Some(Arc::new(cb)));
+        unsafe { (*self.as_mut_ptr()).stop = Some(STOP[self.slot]) };
+
+        self
+    }
+
+    pub fn with_unload<F>(&mut self, cb: F) -> &mut Self
+    where
+        F: Fn() -> Result<(), Error> + Send + Sync + 'static,
+    {
+        self.set_callbacks(|c| c.unload = Some(Arc::new(cb)));
+        unsafe { (*self.as_mut_ptr()).unload = Some(UNLOAD[self.slot]) };
+
+        self
+    }
+
+    /** Called for incoming requests outside of any transaction or dialog
+     * handled by a module of higher priority. */
+    pub fn with_on_rx_request<F>(&mut self, cb: F) -> &mut Self
+    where
+        F: Fn(&mut PjSipRxData) -> PjSipModuleAction + Send + Sync + 'static,
+    {
+        self.set_callbacks(|c| c.on_rx_request = Some(Arc::new(cb)));
+        unsafe { (*self.as_mut_ptr()).on_rx_request = Some(ON_RX_REQUEST[self.slot]) };
+
+        self
+    }
+
+    /** Called for incoming responses not matched by a transaction or
+     * dialog of a module with higher priority. */
+    pub fn with_on_rx_response<F>(&mut self, cb: F) -> &mut Self
+    where
+        F: Fn(&mut PjSipRxData) -> PjSipModuleAction + Send + Sync + 'static,
+    {
+        self.set_callbacks(|c| c.on_rx_response = Some(Arc::new(cb)));
+        unsafe { (*self.as_mut_ptr()).on_rx_response = Some(ON_RX_RESPONSE[self.slot]) };
+
+        self
+    }
+
+    /** Called for every outgoing request before it is sent, the message
+     * can still be modified. An error stops the request from being sent. */
+    pub fn with_on_tx_request<F>(&mut self, cb: F) -> &mut Self
+    where
+        F: Fn(&mut PjSipTxData) -> Result<(), Error> + Send + Sync + 'static,
+    {
+        self.set_callbacks(|c| c.on_tx_request = Some(Arc::new(cb)));
+        unsafe { (*self.as_mut_ptr()).on_tx_request = Some(ON_TX_REQUEST[self.slot]) };
+
+        self
+    }
+
+    /** Same as `with_on_tx_request` for outgoing responses. */
+    pub fn with_on_tx_response<F>(&mut self, cb: F) -> &mut Self
+    where
+        F: Fn(&mut PjSipTxData) -> Result<(), Error> + Send + Sync + 'static,
+    {
+        self.set_callbacks(|c| c.on_tx_response = Some(Arc::new(cb)));
+        unsafe { (*self.as_mut_ptr()).on_tx_response = Some(ON_TX_RESPONSE[self.slot]) };
+
+        self
+    }
+
+    /** Called when a transaction owned by this module changes state. */
+    pub fn with_on_tsx_state<F>(&mut self, cb: F) -> &mut Self
+    where
+        F: Fn(&mut PjSipTransaction, &mut PjSipEvent) + Send + Sync + 'static,
+    {
+        self.set_callbacks(|c| c.on_tsx_state = Some(Arc::new(cb)));
+        unsafe { (*self.as_mut_ptr()).on_tsx_state = Some(ON_TSX_STATE[self.slot]) };
+
+        self
+    }
+
     pub fn id(&self) -> i32 {
         self.pjsip_module.id()
     }
@@ -59,12 +399,99 @@ impl Drop for PjSipModule {
             }
         }
 
+        MODULE_SLOTS[self.slot].write().take();
+
         let name = unsafe { CString::from_raw(module.name.ptr) };
         drop(name);
         drop(module);
     }
 }
 
+/* Data a module attaches to a dialog, session or transaction. The object's
+ * mod_data slot for the module holds a boxed cell, created by the first set
+ * and released by a handler on the object's group lock when pjsip destroys
+ * the object. The cell is only read and written with the group lock held. */
+type ModData = Arc<dyn Any + Send + Sync>;
+type ModDataCell = Option<ModData>;
+
+unsafe extern "C" fn release_mod_data(cell: *mut c_void) {
+    drop(Box::from_raw(cell as *mut ModDataCell));
+}
+
+fn mod_data_slot<const N: usize>(module: &PjSipModule) -> Option<usize> {
+    usize::try_from(module.id()).ok().filter(|id| *id < N)
+}
+
+pub(crate) unsafe fn set_mod_data<D: Any + Send + Sync, const N: usize>(
+    mod_data: *mut [*mut c_void; N],
+    grp_lock: *mut pj::pj_grp_lock_t,
+    pool: *mut pj::pj_pool_t,
+    module: &PjSipModule,
+    data: D,
+) -> Result<(), Error> {
+    let slot = mod_data_slot::<N>(module)
+        .ok_or_else(|| Error::Validation("Module is not registered".into()))?;
+    let slot = (mod_data as *mut *mut c_void).add(slot);
+
+    pj::pj_grp_lock_acquire(grp_lock);
+    let mut cell = *slot as *mut ModDataCell;
+    if cell.is_null() {
+        cell = Box::into_raw(Box::new(None));
+        let status =
+            pj::pj_grp_lock_add_handler(grp_lock, pool, cell as *mut _, Some(release_mod_data));
+        if let Err(err) = PjStatus::result_for_status(status) {
+            pj::pj_grp_lock_release(grp_lock);
+            release_mod_data(cell as *mut _);
+            return Err(err);
+        }
+        *slot = cell as *mut _;
+    }
+    let replaced = (*cell).replace(Arc::new(data));
+    pj::pj_grp_lock_release(grp_lock);
+
+    /* Outside the lock, dropping may run any code */
+    drop(replaced);
+
+    Ok(())
+}
+
+pub(crate) unsafe fn get_mod_data<D: Any + Send + Sync, const N: usize>(
+    mod_data: *const [*mut c_void; N],
+    grp_lock: *mut pj::pj_grp_lock_t,
+    module: &PjSipModule,
+) -> Option<Arc<D>> {
+    let slot = (mod_data as *const *mut c_void).add(mod_data_slot::<N>(module)?);
+
+    pj::pj_grp_lock_acquire(grp_lock);
+    let cell = *slot as *const ModDataCell;
+    let data = if cell.is_null() {
+        None
+    } else {
+        (*cell).clone()
+    };
+    pj::pj_grp_lock_release(grp_lock);
+
+    data?.downcast::<D>().ok()
+}
+
+pub(crate) unsafe fn remove_mod_data<const N: usize>(
+    mod_data: *mut [*mut c_void; N],
+    grp_lock: *mut pj::pj_grp_lock_t,
+    module: &PjSipModule,
+) {
+    let Some(slot) = mod_data_slot::<N>(module) else {
+        return;
+    };
+    let slot = (mod_data as *mut *mut c_void).add(slot);
+
+    pj::pj_grp_lock_acquire(grp_lock);
+    let cell = *slot as *mut ModDataCell;
+    let removed = if cell.is_null() { None } else { (*cell).take() };
+    pj::pj_grp_lock_release(grp_lock);
+
+    drop(removed);
+}
+
 pub struct PjSipModuleRef {
     pjsip_module: *const pj::pjsip_module,
 }
@@ -0,0 +1,153 @@
+use std::{
+    ffi::{CStr, CString},
+    fmt::{Debug, Display},
+    os::raw::c_char,
+};
+
+use pjproject_sys as pj;
+
+use super::sip_uri::quote;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum PjSipMethodId {
+    Invite,
+    Cancel,
+    Ack,
+    Bye,
+    Register,
+    Options,
+    Other,
+}
+
+impl From<u8> for PjSipMethodId {
+    fn from(value: u8) -> Self {
+        match value as pj::pjsip_method_e {
+            pj::pjsip_method_e_PJSIP_INVITE_METHOD => Self::Invite,
+            pj::pjsip_method_e_PJSIP_CANCEL_METHOD => Self::Cancel,
+            pj::pjsip_method_e_PJSIP_ACK_METHOD => Self::Ack,
+            pj::pjsip_method_e_PJSIP_BYE_METHOD => Self::Bye,
+            pj::pjsip_method_e_PJSIP_REGISTER_METHOD => Self::Register,
+            pj::pjsip_method_e_PJSIP_OPTIONS_METHOD => Self::Options,
+            _ => Self::Other,
+        }
+    }
+}
+
+impl Display for PjSipMethodId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                PjSipMethodId::Invite => "INVITE",
+                PjSipMethodId::Cancel => "CANCEL",
+                PjSipMethodId::Ack => "ACK",
+                PjSipMethodId::Bye => "BYE",
+                PjSipMethodId::Register => "REGISTER",
+                PjSipMethodId::Options => "OPTIONS",
+                PjSipMethodId::Other => "OTHER",
+            }
+        )
+    }
+}
+
+impl Debug for PjSipMethodId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{self}[{}]", *self as u8)
+    }
+}
+
+/** Protocol of the cause in a `Reason` header. */
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum PjSipReasonProtocol {
+    /** Cause is a SIP status code. */
+    Sip,
+    /** Cause is an ISDN Q.850 cause value. */
+    Q850,
+}
+
+impl Display for PjSipReasonProtocol {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                PjSipReasonProtocol::Sip => "SIP",
+                PjSipReasonProtocol::Q850 => "Q.850",
+            }
+        )
+    }
+}
+
+/** Value of an RFC 3326 `Reason` header, e.g.
+ * `SIP ;cause=486 ;text="Busy Here"`. */
+pub struct PjSipReason {
+    protocol: PjSipReasonProtocol,
+    cause: u16,
+    text: Option<String>,
+}
+
+impl PjSipReason {
+    pub const HEADER_NAME: &'static CStr = c"Reason";
+
+    pub fn new(protocol: PjSipReasonProtocol, cause: u16) -> Self {
+        Self {
+            protocol,
+            cause,
+            text: None,
+        }
+    }
+
+    pub fn sip(cause: u16) -> Self {
+        Self::new(PjSipReasonProtocol::Sip, cause)
+    }
+
+    pub fn q850(cause: u16) -> Self {
+        Self::new(PjSipReasonProtocol::Q850, cause)
+    }
+
+    pub fn with_text<S: AsRef<CStr>>(&mut self, text: S) -> &mut Self {
+        self.text = Some(text.as_ref().to_string_lossy().into_owned());
+
+        self
+    }
+
+    /** Header value, ready to pass with `HEADER_NAME` as an extra header. */
+    pub fn value(&self) -> CString {
+        CString::new(self.to_string()).expect("Reason header value has no NUL bytes")
+    }
+}
+
+impl Display for PjSipReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} ;cause={}", self.protocol, self.cause)?;
+        if let Some(text) = self.text.as_ref() {
+            write!(f, " ;text=\"{}\"", quote(text))?;
+        }
+
+        Ok(())
+    }
+}
+
+/* Print a header and return what follows the colon, which works for any
+ * header type pjsip knows how to print. */
+pub(crate) unsafe fn hdr_value(hdr: *const pj::pjsip_hdr) -> String {
+    let mut buf = vec![0 as c_char; 256];
+    loop {
+        let len = pj::pjsip_hdr_print_on(hdr as *mut _, buf.as_mut_ptr(), buf.len() as _);
+
+        /* pjsip returns -1 when the buffer is too small */
+        if len < 0 && buf.len() < 65536 {
+            buf.resize(buf.len() * 2, 0);
+            continue;
+        }
+
+        let printed = std::slice::from_raw_parts(buf.as_ptr() as *const u8, len.max(0) as usize);
+        let printed = String::from_utf8_lossy(printed);
+        return match printed.split_once(':') {
+            Some((_, value)) => value.trim().to_owned(),
+            None => printed.trim().to_owned(),
+        };
+    }
+}
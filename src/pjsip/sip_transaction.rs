@@ -0,0 +1,280 @@
+use std::{
+    any::Any,
+    fmt::{Debug, Display},
+    sync::Arc,
+};
+
+use pjproject_sys as pj;
+
+use crate::{
+    get_mod_data, pj_str_to_string, remove_mod_data, set_mod_data, Error, PjSipDialog,
+    PjSipMethodId, PjSipModule, PjSipRole, PjSipRxData, PjSipTxData, PjStatus,
+};
+
+/** Handle on a client (UAC) or server (UAS) transaction owned by pjsip.
+ * Every handle holds a reference on the transaction's group lock, so the
+ * transaction stays allocated while Rust holds it, also after it reached
+ * `Terminated`. Cloning is cheap. */
+pub struct PjSipTransaction {
+    pjsip_transaction: *mut pj::pjsip_transaction,
+}
+
+unsafe impl Send for PjSipTransaction {}
+unsafe impl Sync for PjSipTransaction {}
+
+impl PjSipTransaction {
+    /** Create a client transaction for the request in `tdata`, owned by
+     * `tsx_user`, whose `on_tsx_state` hook gets the state changes. Send
+     * the request with `send_msg`. */
+    pub fn create_uac(tsx_user: &PjSipModule, tdata: &mut PjSipTxData) -> Result<Self, Error> {
+        let mut pjsip_transaction = std::ptr::null_mut();
+        let status = unsafe {
+            pj::pjsip_tsx_create_uac(
+                tsx_user.as_mut_ptr(),
+                tdata.as_mut_ptr(),
+                &mut pjsip_transaction,
+            )
+        };
+
+        PjStatus::result_for_status(status).map(|_| Self::from(pjsip_transaction))
+    }
+
+    /** Create a server transaction for the request in `rdata`, owned by
+     * `tsx_user`, and feed it the request. Respond with `send_msg`, pjsip
+     * takes care of retransmissions and absorbs retransmitted requests. */
+    pub fn create_uas(tsx_user: &PjSipModule, rdata: &mut PjSipRxData) -> Result<Self, Error> {
+        let mut pjsip_transaction = std::ptr::null_mut();
+        let status = unsafe {
+            pj::pjsip_tsx_create_uas(
+                tsx_user.as_mut_ptr(),
+                rdata.as_mut_ptr(),
+                &mut pjsip_transaction,
+            )
+        };
+        PjStatus::result_for_status(status)?;
+
+        /* The transaction stays in Null until it has seen the request */
+        unsafe { pj::pjsip_tsx_recv_msg(pjsip_transaction, rdata.as_mut_ptr()) };
+
+        Ok(Self::from(pjsip_transaction))
+    }
+
+    /** Transaction the message in `rdata` was matched to, if any. */
+    pub fn from_rx_data(rdata: &mut PjSipRxData) -> Option<Self> {
+        let pjsip_transaction = unsafe { pj::pjsip_rdata_get_tsx(rdata.as_mut_ptr()) };
+
+        (!pjsip_transaction.is_null()).then(|| Self::from(pjsip_transaction))
+    }
+
+    /** Dialog the transaction belongs to, e.g. for a usage's on_tsx_state
+     * to find the dialog of a response. */
+    pub fn dialog(&self) -> Option<PjSipDialog> {
+        let dialog = unsafe { pj::pjsip_tsx_get_dlg(self.pjsip_transaction) };
+
+        (!dialog.is_null()).then(|| PjSipDialog::from(dialog))
+    }
+
+    /** Send the request of a client transaction or a response on a server
+     * transaction. `tdata` is released also when sending fails. */
+    pub fn send_msg(&mut self, mut tdata: PjSipTxData) -> Result<(), Error> {
+        let status = unsafe { pj::pjsip_tsx_send_msg(self.as_mut_ptr(), tdata.as_mut_ptr()) };
+        /* pjsip only takes the reference when sending starts */
+        if status != pj::pj_constants__PJ_SUCCESS as _ {
+            unsafe { pj::pjsip_tx_data_dec_ref(tdata.as_mut_ptr()) };
+        }
+
+        PjStatus::result_for_status(status)
+    }
+
+    /** Terminate the transaction at once, reporting `status_code`, e.g.
+     * 487 for a cancelled request. */
+    pub fn terminate(&mut self, status_code: u16) -> Result<(), Error> {
+        let status = unsafe { pj::pjsip_tsx_terminate(self.as_mut_ptr(), status_code as _) };
+
+        PjStatus::result_for_status(status)
+    }
+
+    pub fn as_ptr(&self) -> *const pj::pjsip_transaction {
+        self.pjsip_transaction
+    }
+
+    pub fn as_mut_ptr(&mut self) -> *mut pj::pjsip_transaction {
+        self.pjsip_transaction
+    }
+
+    pub fn as_ref(&self) -> &pj::pjsip_transaction {
+        unsafe { &*self.pjsip_transaction }
+    }
+
+    pub fn state(&self) -> PjSipTsxState {
+        (self.as_ref().state as u8).into()
+    }
+
+    pub fn method_id(&self) -> PjSipMethodId {
+        (self.as_ref().method.id as u8).into()
+    }
+
+    pub fn role(&self) -> PjSipRole {
+        (self.as_ref().role as u8).into()
+    }
+
+    pub fn cseq(&self) -> i32 {
+        self.as_ref().cseq
+    }
+
+    /** Key the transaction layer knows the transaction by. */
+    pub fn key(&self) -> String {
+        pj_str_to_string(&self.as_ref().transaction_key)
+    }
+
+    pub fn branch(&self) -> String {
+        pj_str_to_string(&self.as_ref().branch)
+    }
+
+    /** Last status code sent or received on the transaction. */
+    pub fn status_code(&self) -> u16 {
+        self.as_ref().status_code as _
+    }
+
+    pub fn status_text(&self) -> String {
+        pj_str_to_string(&self.as_ref().status_text)
+    }
+
+    /** Last message sent on the transaction, e.g. the final response of a
+     * server transaction. */
+    pub fn last_tx(&self) -> Option<PjSipTxData> {
+        let last_tx = self.as_ref().last_tx;
+
+        (!last_tx.is_null()).then(|| PjSipTxData::from(last_tx))
+    }
+
+    /** Attach data for `module` to the transaction, replacing any data it
+     * attached before. The data is released when pjsip destroys the
+     * transaction. */
+    pub fn set_mod_data<D: Any + Send + Sync>(
+        &mut self,
+        module: &PjSipModule,
+        data: D,
+    ) -> Result<(), Error> {
+        unsafe {
+            set_mod_data(
+                std::ptr::addr_of_mut!((*self.pjsip_transaction).mod_data),
+                (*self.pjsip_transaction).grp_lock,
+                (*self.pjsip_transaction).pool,
+                module,
+                data,
+            )
+        }
+    }
+
+    /** Data attached by `module`, if it is of type `D`. */
+    pub fn mod_data<D: Any + Send + Sync>(&self, module: &PjSipModule) -> Option<Arc<D>> {
+        unsafe {
+            get_mod_data(
+                std::ptr::addr_of!((*self.pjsip_transaction).mod_data),
+                (*self.pjsip_transaction).grp_lock,
+                module,
+            )
+        }
+    }
+
+    pub fn remove_mod_data(&mut self, module: &PjSipModule) {
+        unsafe {
+            remove_mod_data(
+                std::ptr::addr_of_mut!((*self.pjsip_transaction).mod_data),
+                (*self.pjsip_transaction).grp_lock,
+                module,
+            )
+        }
+    }
+}
+
+impl Clone for PjSipTransaction {
+    fn clone(&self) -> Self {
+        Self::from(self.pjsip_transaction)
+    }
+}
+
+impl Drop for PjSipTransaction {
+    fn drop(&mut self) {
+        unsafe { pj::pj_grp_lock_dec_ref(self.as_ref().grp_lock) };
+    }
+}
+
+/** Take a new handle on a transaction owned by pjsip. */
+impl From<*mut pj::pjsip_transaction> for PjSipTransaction {
+    #[allow(clippy::not_unsafe_ptr_arg_deref)]
+    fn from(value: *mut pj::pjsip_transaction) -> Self {
+        unsafe { pj::pj_grp_lock_add_ref((*value).grp_lock) };
+
+        Self {
+            pjsip_transaction: value,
+        }
+    }
+}
+
+/** Final outcome of a request sent with `PjSipEndpoint::send_request`. */
+pub struct PjSipResponse<'a> {
+    /** Final status code, pjsip reports 408 when the request timed out and
+     * 503 when it could not be sent. */
+    pub status_code: u16,
+    pub status_text: String,
+    /** The final response, when one was received. */
+    pub rdata: Option<PjSipRxData<'a>>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum PjSipTsxState {
+    Null,
+    Calling,
+    Trying,
+    Proceeding,
+    Completed,
+    Confirmed,
+    Terminated,
+    Destroyed,
+    Unknown,
+}
+
+impl From<u8> for PjSipTsxState {
+    fn from(value: u8) -> Self {
+        match value as pj::pjsip_tsx_state_e {
+            pj::pjsip_tsx_state_e_PJSIP_TSX_STATE_NULL => Self::Null,
+            pj::pjsip_tsx_state_e_PJSIP_TSX_STATE_CALLING => Self::Calling,
+            pj::pjsip_tsx_state_e_PJSIP_TSX_STATE_TRYING => Self::Trying,
+            pj::pjsip_tsx_state_e_PJSIP_TSX_STATE_PROCEEDING => Self::Proceeding,
+            pj::pjsip_tsx_state_e_PJSIP_TSX_STATE_COMPLETED => Self::Completed,
+            pj::pjsip_tsx_state_e_PJSIP_TSX_STATE_CONFIRMED => Self::Confirmed,
+            pj::pjsip_tsx_state_e_PJSIP_TSX_STATE_TERMINATED => Self::Terminated,
+            pj::pjsip_tsx_state_e_PJSIP_TSX_STATE_DESTROYED => Self::Destroyed,
+            _ => Self::Unknown,
+        }
+    }
+}
+
+impl Display for PjSipTsxState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                PjSipTsxState::Null => "Null",
+                PjSipTsxState::Calling => "Calling",
+                PjSipTsxState::Trying => "Trying",
+                PjSipTsxState::Proceeding => "Proceeding",
+                PjSipTsxState::Completed => "Completed",
+                PjSipTsxState::Confirmed => "Confirmed",
+                PjSipTsxState::Terminated => "Terminated",
+                PjSipTsxState::Destroyed => "Destroyed",
+                PjSipTsxState::Unknown => "Unknown",
+            }
+        )
+    }
+}
+
+impl Debug for PjSipTsxState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{self}[{}]", *self as u8)
+    }
+}
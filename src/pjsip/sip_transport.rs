@@ -1,15 +1,25 @@
-use std::ffi::{CStr, CString};
+use std::{
+    ffi::CStr,
+    fmt::{Debug, Display},
+    marker::PhantomData,
+    ops::Deref,
+    sync::Arc,
+};
 
+use parking_lot::RwLock;
 use pjproject_sys as pj;
 
-use crate::{Error, PjSipInvSession, PjStatus};
+use crate::{
+    hdr_value, pj_str_to_string, Error, PjMediaSdpSession, PjMediaSdpSessionRef, PjSipHostPort,
+    PjSipHostPortRef, PjSipInvSession, PjSipMethodId, PjSipUriRef, PjSockaddrRef, PjStatus,
+};
 
 pub struct PjSipTxData {
     pjsip_tx_data: *mut pj::pjsip_tx_data,
 }
 
 impl PjSipTxData {
-    pub fn inv_invite<T>(inv_sess: &PjSipInvSession<T>) -> Result<PjSipTxData, Error> {
+    pub fn inv_invite(inv_sess: &PjSipInvSession) -> Result<PjSipTxData, Error> {
         let mut pjsip_tx_data = std::ptr::null_mut();
         let status = unsafe { pj::pjsip_inv_invite(inv_sess.as_mut_ptr(), &mut pjsip_tx_data) };
 
@@ -20,36 +30,355 @@ impl PjSipTxData {
         PjStatus::result_for_status(status).map(|_| Self { pjsip_tx_data })
     }
 
-    pub fn inv_end_session<T, S: AsRef<CStr>>(
-        inv_sess: &PjSipInvSession<T>,
+    /** Create the request or response that ends the session: CANCEL while
+     * our INVITE is pending, a final response with `status_code` while an
+     * incoming INVITE is unanswered, or BYE once the call is up. Returns
+     * `None` when there is nothing to send yet, e.g. a CANCEL is deferred
+     * until the peer sends a provisional response. */
+    pub fn inv_end_session<S: AsRef<CStr>>(
+        inv_sess: &PjSipInvSession,
+        status_code: u16,
         status_text: Option<S>,
-    ) -> Result<PjSipTxData, Error> {
+    ) -> Result<Option<PjSipTxData>, Error> {
         let mut pjsip_tx_data = std::ptr::null_mut();
         let st_text = status_text
             .as_ref()
-            .map(|s| s.as_ref().to_owned().into_raw())
-            .unwrap_or(std::ptr::null_mut());
+            .map(|s| unsafe { pj::pj_str(s.as_ref().as_ptr() as *mut _) });
 
         let status = unsafe {
             pj::pjsip_inv_end_session(
                 inv_sess.as_mut_ptr(),
-                603,
-                &pj::pj_str(st_text),
+                status_code as _,
+                st_text
+                    .as_ref()
+                    .map(|s| s as *const _)
+                    .unwrap_or(std::ptr::null()),
+                &mut pjsip_tx_data,
+            )
+        };
+
+        PjStatus::result_for_status(status)
+            .map(|_| (!pjsip_tx_data.is_null()).then_some(Self { pjsip_tx_data }))
+    }
+
+    pub fn inv_initial_answer<S: AsRef<CStr>>(
+        inv_sess: &PjSipInvSession,
+        rdata: &mut PjSipRxData,
+        status_code: u16,
+        status_text: Option<S>,
+        local_sdp: Option<&PjMediaSdpSession>,
+    ) -> Result<PjSipTxData, Error> {
+        let mut pjsip_tx_data = std::ptr::null_mut();
+        let st_text = status_text
+            .as_ref()
+            .map(|s| unsafe { pj::pj_str(s.as_ref().as_ptr() as *mut _) });
+
+        let status = unsafe {
+            pj::pjsip_inv_initial_answer(
+                inv_sess.as_mut_ptr(),
+                rdata.as_mut_ptr(),
+                status_code as _,
+                st_text
+                    .as_ref()
+                    .map(|s| s as *const _)
+                    .unwrap_or(std::ptr::null()),
+                local_sdp.map(|s| s.as_ptr()).unwrap_or(std::ptr::null()),
+                &mut pjsip_tx_data,
+            )
+        };
+
+        PjStatus::result_for_status(status)?;
+        if pjsip_tx_data.is_null() {
+            return Err(Error::Validation("Got null creating PjSipTxData".into()));
+        }
+
+        Ok(Self { pjsip_tx_data })
+    }
+
+    pub fn inv_answer<S: AsRef<CStr>>(
+        inv_sess: &PjSipInvSession,
+        status_code: u16,
+        status_text: Option<S>,
+        local_sdp: Option<&PjMediaSdpSession>,
+    ) -> Result<PjSipTxData, Error> {
+        let mut pjsip_tx_data = std::ptr::null_mut();
+        let st_text = status_text
+            .as_ref()
+            .map(|s| unsafe { pj::pj_str(s.as_ref().as_ptr() as *mut _) });
+
+        let status = unsafe {
+            pj::pjsip_inv_answer(
+                inv_sess.as_mut_ptr(),
+                status_code as _,
+                st_text
+                    .as_ref()
+                    .map(|s| s as *const _)
+                    .unwrap_or(std::ptr::null()),
+                local_sdp.map(|s| s.as_ptr()).unwrap_or(std::ptr::null()),
+                &mut pjsip_tx_data,
+            )
+        };
+
+        PjStatus::result_for_status(status)?;
+        if pjsip_tx_data.is_null() {
+            return Err(Error::Validation("Got null creating PjSipTxData".into()));
+        }
+
+        Ok(Self { pjsip_tx_data })
+    }
+
+    /** Create a re-INVITE, offering `new_sdp` or, if `None`, an offer from
+     * `on_create_offer` or the current local SDP. */
+    pub fn inv_reinvite(
+        inv_sess: &PjSipInvSession,
+        new_sdp: Option<&PjMediaSdpSessionRef>,
+    ) -> Result<PjSipTxData, Error> {
+        let mut pjsip_tx_data = std::ptr::null_mut();
+        let status = unsafe {
+            pj::pjsip_inv_reinvite(
+                inv_sess.as_mut_ptr(),
+                std::ptr::null(),
+                new_sdp.map(|s| s.as_ptr()).unwrap_or(std::ptr::null()),
                 &mut pjsip_tx_data,
             )
         };
 
+        PjStatus::result_for_status(status)?;
+        if pjsip_tx_data.is_null() {
+            return Err(Error::Validation("Got null creating PjSipTxData".into()));
+        }
+
+        Ok(Self { pjsip_tx_data })
+    }
+
+    /** Create an UPDATE, offering `new_sdp` if given. */
+    pub fn inv_update(
+        inv_sess: &PjSipInvSession,
+        new_sdp: Option<&PjMediaSdpSessionRef>,
+    ) -> Result<PjSipTxData, Error> {
+        let mut pjsip_tx_data = std::ptr::null_mut();
+        let status = unsafe {
+            pj::pjsip_inv_update(
+                inv_sess.as_mut_ptr(),
+                std::ptr::null(),
+                new_sdp.map(|s| s.as_ptr()).unwrap_or(std::ptr::null()),
+                &mut pjsip_tx_data,
+            )
+        };
+
+        PjStatus::result_for_status(status)?;
+        if pjsip_tx_data.is_null() {
+            return Err(Error::Validation("Got null creating PjSipTxData".into()));
+        }
+
+        Ok(Self { pjsip_tx_data })
+    }
+
+    /** Create the ACK for a 2xx response to the INVITE with the given
+     * CSeq. */
+    pub fn inv_ack(inv_sess: &PjSipInvSession, cseq: i32) -> Result<PjSipTxData, Error> {
+        let mut pjsip_tx_data = std::ptr::null_mut();
+        let status =
+            unsafe { pj::pjsip_inv_create_ack(inv_sess.as_mut_ptr(), cseq, &mut pjsip_tx_data) };
+
+        PjStatus::result_for_status(status)?;
         if pjsip_tx_data.is_null() {
             return Err(Error::Validation("Got null creating PjSipTxData".into()));
         }
 
+        Ok(Self { pjsip_tx_data })
+    }
+
+    fn pool(&self) -> *mut pj::pj_pool_t {
+        unsafe { (*self.pjsip_tx_data).pool }
+    }
+
+    fn msg_hdr(&mut self) -> *mut pj::pjsip_hdr {
+        unsafe { &mut (*(*self.pjsip_tx_data).msg).hdr }
+    }
+
+    /* Any change to the message must drop the already printed buffer, if
+     * any, so the message is printed again before sending. */
+    fn invalidate(&mut self) {
+        unsafe { pj::pjsip_tx_data_invalidate_msg(self.pjsip_tx_data) };
+    }
+
+    /** Append a header with a free form value, e.g. `Reason` or an `X-`
+     * header. */
+    pub fn add_header<S: AsRef<CStr>, V: AsRef<CStr>>(
+        &mut self,
+        name: S,
+        value: V,
+    ) -> Result<(), Error> {
         unsafe {
-            if status_text.is_some() {
-                let _ = CString::from_raw(st_text);
+            /* pjsip copies the name and value into the pool */
+            let hdr = pj::pjsip_generic_string_hdr_create(
+                self.pool(),
+                &pj::pj_str(name.as_ref().as_ptr() as *mut _),
+                &pj::pj_str(value.as_ref().as_ptr() as *mut _),
+            );
+            if hdr.is_null() {
+                return Err(Error::Validation("Failed to create header".into()));
             }
+
+            pj::pj_list_insert_before(self.msg_hdr() as *mut _, hdr as *mut _);
         }
+        self.invalidate();
 
-        PjStatus::result_for_status(status).map(|_| Self { pjsip_tx_data })
+        Ok(())
+    }
+
+    /** Append a header parsed by pjsip, so it is stored as its typed
+     * header, e.g. a Contact or Route. Fails if the value does not parse. */
+    pub fn add_parsed_header<S: AsRef<CStr>, V: AsRef<CStr>>(
+        &mut self,
+        name: S,
+        value: V,
+    ) -> Result<(), Error> {
+        unsafe {
+            /* The parsed header points into the name and the buffer, e.g.
+             * a generic header for a name pjsip does not know, so both have
+             * to live in the message's pool. */
+            let mut hname = std::mem::zeroed::<pj::pj_str_t>();
+            pj::pj_strdup2_with_null(self.pool(), &mut hname, name.as_ref().as_ptr());
+            let mut line = std::mem::zeroed::<pj::pj_str_t>();
+            pj::pj_strdup2_with_null(self.pool(), &mut line, value.as_ref().as_ptr());
+
+            let hdr = pj::pjsip_parse_hdr(
+                self.pool(),
+                &hname,
+                line.ptr,
+                line.slen as _,
+                std::ptr::null_mut(),
+            );
+            if hdr.is_null() {
+                return Err(Error::Validation(format!(
+                    "Failed to parse {} header",
+                    name.as_ref().to_string_lossy()
+                )));
+            }
+
+            /* A value with several comma separated entries parses into a
+             * list of headers */
+            pj::pj_list_insert_nodes_before(self.msg_hdr() as *mut _, hdr);
+        }
+        self.invalidate();
+
+        Ok(())
+    }
+
+    /** Remove all headers with the given name and return how many were
+     * removed. */
+    pub fn remove_header<S: AsRef<CStr>>(&mut self, name: S) -> usize {
+        let name = unsafe { pj::pj_str(name.as_ref().as_ptr() as *mut _) };
+
+        let mut removed = 0;
+        loop {
+            let hdr = unsafe {
+                pj::pjsip_msg_find_hdr_by_name((*self.pjsip_tx_data).msg, &name, std::ptr::null())
+            };
+            if hdr.is_null() {
+                break;
+            }
+            unsafe { pj::pj_list_erase(hdr) };
+            removed += 1;
+        }
+        if removed > 0 {
+            self.invalidate();
+        }
+
+        removed
+    }
+
+    /** Replace all headers with the given name by a single one. */
+    pub fn replace_header<S: AsRef<CStr>, V: AsRef<CStr>>(
+        &mut self,
+        name: S,
+        value: V,
+    ) -> Result<(), Error> {
+        self.remove_header(name.as_ref());
+        self.add_header(name, value)
+    }
+
+    /** Value of the first header with the given name. */
+    pub fn header<S: AsRef<CStr>>(&self, name: S) -> Option<String> {
+        let name = unsafe { pj::pj_str(name.as_ref().as_ptr() as *mut _) };
+        let hdr = unsafe {
+            pj::pjsip_msg_find_hdr_by_name((*self.pjsip_tx_data).msg, &name, std::ptr::null())
+        };
+
+        (!hdr.is_null()).then(|| unsafe { hdr_value(hdr as *const pj::pjsip_hdr) })
+    }
+
+    /** Add a Contact, e.g. `<sip:alice@10.0.0.1:5060>`. */
+    pub fn add_contact<S: AsRef<CStr>>(&mut self, contact: S) -> Result<(), Error> {
+        self.add_parsed_header(c"Contact", contact)
+    }
+
+    /** Add a Route, e.g. `<sip:proxy.example.com;lr>`. */
+    pub fn add_route<S: AsRef<CStr>>(&mut self, route: S) -> Result<(), Error> {
+        self.add_parsed_header(c"Route", route)
+    }
+
+    pub fn set_user_agent<S: AsRef<CStr>>(&mut self, user_agent: S) -> Result<(), Error> {
+        self.replace_header(c"User-Agent", user_agent)
+    }
+
+    /** Set the P-Asserted-Identity (RFC 3325), e.g.
+     * `"Alice" <sip:+15551234567@example.com>`. */
+    pub fn set_p_asserted_identity<S: AsRef<CStr>>(&mut self, identity: S) -> Result<(), Error> {
+        self.replace_header(c"P-Asserted-Identity", identity)
+    }
+
+    /** Set the message body, replacing any existing one. `content_type` is
+     * `type/subtype`, e.g. `application/sdp`. */
+    pub fn set_body<S: AsRef<CStr>>(&mut self, content_type: S, body: &[u8]) -> Result<(), Error> {
+        let body = create_body(self.pool(), content_type.as_ref(), body)?;
+        unsafe { (*(*self.pjsip_tx_data).msg).body = body };
+        self.invalidate();
+
+        Ok(())
+    }
+
+    /** Set a `multipart/mixed` body with one part per content type and
+     * data pair. */
+    pub fn set_multipart_body(&mut self, parts: &[(&CStr, &[u8])]) -> Result<(), Error> {
+        let pool = self.pool();
+        let mp = unsafe { pj::pjsip_multipart_create(pool, std::ptr::null(), std::ptr::null()) };
+        if mp.is_null() {
+            return Err(Error::Validation("Failed to create multipart body".into()));
+        }
+
+        for (content_type, data) in parts {
+            unsafe {
+                let part = pj::pjsip_multipart_create_part(pool);
+                if part.is_null() {
+                    return Err(Error::Validation("Failed to create multipart part".into()));
+                }
+                (*part).body = create_body(pool, content_type, data)?;
+
+                let status = pj::pjsip_multipart_add_part(pool, mp, part);
+                PjStatus::result_for_status(status)?;
+            }
+        }
+
+        unsafe { (*(*self.pjsip_tx_data).msg).body = mp };
+        self.invalidate();
+
+        Ok(())
+    }
+
+    /** Send the message over the selected transport or listener. */
+    pub fn set_transport(&mut self, selector: &PjSipTpSelector) -> Result<(), Error> {
+        let status =
+            unsafe { pj::pjsip_tx_data_set_transport(self.pjsip_tx_data, selector.as_ptr()) };
+
+        PjStatus::result_for_status(status)
+    }
+
+    pub fn remove_body(&mut self) {
+        unsafe { (*(*self.pjsip_tx_data).msg).body = std::ptr::null_mut() };
+        self.invalidate();
     }
 
     pub fn as_ptr(&self) -> *const pj::pjsip_tx_data {
@@ -60,3 +389,706 @@ impl PjSipTxData {
         self.pjsip_tx_data
     }
 }
+
+/* Fill `hdr_list`, a list head that must not move while in use, with
+ * free form headers allocated in `pool`. */
+pub(crate) fn create_hdr_list(
+    pool: *mut pj::pj_pool_t,
+    headers: &[(&CStr, &CStr)],
+    hdr_list: &mut pj::pjsip_hdr,
+) -> Result<(), Error> {
+    hdr_list.prev = hdr_list;
+    hdr_list.next = hdr_list;
+
+    for (name, value) in headers {
+        unsafe {
+            let hdr = pj::pjsip_generic_string_hdr_create(
+                pool,
+                &pj::pj_str(name.as_ptr() as *mut _),
+                &pj::pj_str(value.as_ptr() as *mut _),
+            );
+            if hdr.is_null() {
+                return Err(Error::Validation("Failed to create header".into()));
+            }
+
+            pj::pj_list_insert_before(hdr_list as *mut _ as *mut _, hdr as *mut _);
+        }
+    }
+
+    Ok(())
+}
+
+/* Create a body in `pool`, pjsip copies the data. */
+pub(crate) fn create_body(
+    pool: *mut pj::pj_pool_t,
+    content_type: &CStr,
+    data: &[u8],
+) -> Result<*mut pj::pjsip_msg_body, Error> {
+    let (type_, subtype) = content_type
+        .to_str()
+        .ok()
+        .and_then(|c| c.split_once('/'))
+        .ok_or_else(|| {
+            Error::Validation(format!(
+                "Content type must be type/subtype, got {}",
+                content_type.to_string_lossy()
+            ))
+        })?;
+
+    let str_of = |s: &str| pj::pj_str_t {
+        ptr: s.as_ptr() as *mut _,
+        slen: s.len() as _,
+    };
+    let body = unsafe {
+        pj::pjsip_msg_body_create(
+            pool,
+            &str_of(type_),
+            &str_of(subtype),
+            &pj::pj_str_t {
+                ptr: data.as_ptr() as *mut _,
+                slen: data.len() as _,
+            },
+        )
+    };
+    if body.is_null() {
+        return Err(Error::Validation("Failed to create message body".into()));
+    }
+
+    Ok(body)
+}
+
+impl From<*mut pj::pjsip_tx_data> for PjSipTxData {
+    fn from(value: *mut pj::pjsip_tx_data) -> Self {
+        Self {
+            pjsip_tx_data: value,
+        }
+    }
+}
+
+/** Incoming message buffer. Only valid for the duration of the callback
+ * it was handed to, pjsip reuses the buffer once the callback returns. */
+pub struct PjSipRxData<'a> {
+    pjsip_rx_data: *mut pj::pjsip_rx_data,
+    phantom: PhantomData<&'a mut pj::pjsip_rx_data>,
+}
+
+impl<'a> PjSipRxData<'a> {
+    pub fn as_ptr(&self) -> *const pj::pjsip_rx_data {
+        self.pjsip_rx_data
+    }
+
+    pub fn as_mut_ptr(&mut self) -> *mut pj::pjsip_rx_data {
+        self.pjsip_rx_data
+    }
+
+    pub fn as_ref(&self) -> &pj::pjsip_rx_data {
+        unsafe { &*self.pjsip_rx_data }
+    }
+
+    pub fn info(&self) -> &CStr {
+        unsafe { CStr::from_ptr(pj::pjsip_rx_data_get_info(self.pjsip_rx_data)) }
+    }
+
+    pub fn is_request(&self) -> bool {
+        unsafe { (*self.as_ref().msg_info.msg).type_ == pj::pjsip_msg_type_e_PJSIP_REQUEST_MSG }
+    }
+
+    fn msg(&self) -> &pj::pjsip_msg {
+        unsafe { &*self.as_ref().msg_info.msg }
+    }
+
+    fn method(&self) -> &pj::pjsip_method {
+        unsafe {
+            if self.is_request() {
+                &self.msg().line.req.method
+            } else {
+                &(*self.as_ref().msg_info.cseq).method
+            }
+        }
+    }
+
+    /** Method of the request, or of the CSeq header for a response. */
+    pub fn method_id(&self) -> PjSipMethodId {
+        (self.method().id as u8).into()
+    }
+
+    /** Method name as it appears in the message, also for methods without
+     * a `PjSipMethodId`. */
+    pub fn method_name(&self) -> String {
+        pj_str_to_string(&self.method().name)
+    }
+
+    pub fn status_code(&self) -> Option<u16> {
+        (!self.is_request()).then(|| unsafe { self.msg().line.status.code as _ })
+    }
+
+    pub fn status_text(&self) -> Option<String> {
+        (!self.is_request()).then(|| unsafe { pj_str_to_string(&self.msg().line.status.reason) })
+    }
+
+    pub fn request_uri(&self) -> Option<&'a PjSipUriRef> {
+        self.is_request().then(|| unsafe {
+            PjSipUriRef::from_field(&(*self.as_ref().msg_info.msg).line.req.uri)
+        })
+    }
+
+    pub fn from_uri(&self) -> &'a PjSipUriRef {
+        unsafe { PjSipUriRef::from_field(&(*self.as_ref().msg_info.from).uri) }
+    }
+
+    pub fn from_tag(&self) -> String {
+        pj_str_to_string(&self.as_ref().msg_info.from_tag)
+    }
+
+    pub fn to_uri(&self) -> &'a PjSipUriRef {
+        unsafe { PjSipUriRef::from_field(&(*self.as_ref().msg_info.to).uri) }
+    }
+
+    pub fn to_tag(&self) -> String {
+        pj_str_to_string(&self.as_ref().msg_info.to_tag)
+    }
+
+    pub fn call_id(&self) -> String {
+        unsafe { pj_str_to_string(&(*self.as_ref().msg_info.cid).id) }
+    }
+
+    pub fn cseq(&self) -> u32 {
+        unsafe { (*self.as_ref().msg_info.cseq).cseq as _ }
+    }
+
+    /** Value of the first header with the given name, matched case
+     * insensitively against the full header name. */
+    pub fn header<S: AsRef<CStr>>(&self, name: S) -> Option<String> {
+        self.headers(name).into_iter().next()
+    }
+
+    /** Values of all headers with the given name, in message order. */
+    pub fn headers<S: AsRef<CStr>>(&self, name: S) -> Vec<String> {
+        let name = unsafe { pj::pj_str(name.as_ref().as_ptr() as *mut _) };
+        let msg = self.as_ref().msg_info.msg;
+
+        let mut values = Vec::new();
+        let mut start = std::ptr::null();
+        loop {
+            let hdr = unsafe { pj::pjsip_msg_find_hdr_by_name(msg, &name, start) };
+            if hdr.is_null() {
+                return values;
+            }
+            values.push(unsafe { hdr_value(hdr as *const pj::pjsip_hdr) });
+            start = unsafe { (*(hdr as *const pj::pjsip_hdr)).next as *const _ };
+        }
+    }
+
+    pub fn body(&self) -> Option<&[u8]> {
+        let body = self.msg().body;
+        if body.is_null() {
+            return None;
+        }
+
+        unsafe {
+            let body = &*body;
+            if body.data.is_null() {
+                return Some(&[]);
+            }
+            Some(std::slice::from_raw_parts(
+                body.data as *const u8,
+                body.len as usize,
+            ))
+        }
+    }
+
+    /** Content type of the body as `type/subtype`. */
+    pub fn content_type(&self) -> Option<String> {
+        let body = self.msg().body;
+        if body.is_null() {
+            return None;
+        }
+
+        let content_type = unsafe { &(*body).content_type };
+        Some(format!(
+            "{}/{}",
+            pj_str_to_string(&content_type.type_),
+            pj_str_to_string(&content_type.subtype)
+        ))
+    }
+
+    pub fn src_addr(&self) -> PjSockaddrRef<'_> {
+        PjSockaddrRef::from(&self.as_ref().pkt_info.src_addr)
+    }
+
+    /** Source address as printed by pjsip, without the port. */
+    pub fn src_name(&self) -> &CStr {
+        unsafe { CStr::from_ptr(self.as_ref().pkt_info.src_name.as_ptr()) }
+    }
+
+    pub fn src_port(&self) -> u16 {
+        self.as_ref().pkt_info.src_port as _
+    }
+
+    /** Transport the message was received on. */
+    pub fn transport(&self) -> &'a PjSipTransportRef {
+        unsafe { PjSipTransportRef::from_field(&(*self.pjsip_rx_data).tp_info.transport) }
+    }
+}
+
+impl<'a> From<*mut pj::pjsip_rx_data> for PjSipRxData<'a> {
+    fn from(value: *mut pj::pjsip_rx_data) -> Self {
+        Self {
+            pjsip_rx_data: value,
+            phantom: PhantomData,
+        }
+    }
+}
+
+/** Transport handed out by pjsip, e.g. in a callback, valid as long as
+ * the object it was taken from. Use `PjSipTransport` to keep one. */
+#[repr(transparent)]
+pub struct PjSipTransportRef {
+    pjsip_transport: *mut pj::pjsip_transport,
+}
+
+impl PjSipTransportRef {
+    /* View the transport pointer stored in `transport`, borrowed as long as
+     * the object holding it */
+    pub(crate) unsafe fn from_field(transport: &*mut pj::pjsip_transport) -> &Self {
+        &*(transport as *const *mut pj::pjsip_transport as *const Self)
+    }
+
+    pub fn as_ptr(&self) -> *const pj::pjsip_transport {
+        self.pjsip_transport
+    }
+
+    pub fn as_mut_ptr(&self) -> *mut pj::pjsip_transport {
+        self.pjsip_transport
+    }
+
+    pub fn as_ref(&self) -> &pj::pjsip_transport {
+        unsafe { &*self.pjsip_transport }
+    }
+
+    pub fn transport_type(&self) -> PjSipTransportType {
+        (self.as_ref().key.type_ as u8).into()
+    }
+
+    /** Transport type, e.g. "UDP" or "TLS". */
+    pub fn type_name(&self) -> &CStr {
+        unsafe { CStr::from_ptr(self.as_ref().type_name) }
+    }
+
+    pub fn info(&self) -> &CStr {
+        unsafe { CStr::from_ptr(self.as_ref().info) }
+    }
+
+    /** Address the transport is bound to. */
+    pub fn local_addr(&self) -> PjSockaddrRef<'_> {
+        (&self.as_ref().local_addr).into()
+    }
+
+    /** Address published in Via and Contact. */
+    pub fn local_name(&self) -> PjSipHostPortRef {
+        (&self.as_ref().local_name).into()
+    }
+
+    pub fn remote_name(&self) -> PjSipHostPortRef {
+        (&self.as_ref().remote_name).into()
+    }
+
+    pub fn dir(&self) -> PjSipTransportDir {
+        (self.as_ref().dir as u8).into()
+    }
+
+    pub fn ref_count(&self) -> i64 {
+        unsafe { pj::pj_atomic_get(self.as_ref().ref_cnt) as _ }
+    }
+
+    pub fn is_shutdown(&self) -> bool {
+        self.as_ref().is_shutdown != pj::pj_constants__PJ_FALSE as pj::pj_bool_t
+    }
+
+    /** Stop using the transport for new messages, it is destroyed once
+     * the messages using it are done. */
+    pub fn shutdown(&self) -> Result<(), Error> {
+        let status = unsafe { pj::pjsip_transport_shutdown(self.as_mut_ptr()) };
+
+        PjStatus::result_for_status(status)
+    }
+}
+
+impl From<*mut pj::pjsip_transport> for PjSipTransportRef {
+    fn from(value: *mut pj::pjsip_transport) -> Self {
+        Self {
+            pjsip_transport: value,
+        }
+    }
+}
+
+/** Counted reference to a transport, pjsip keeps the transport alive, even
+ * when idle, until the last one is dropped. */
+pub struct PjSipTransport {
+    pjsip_transport: PjSipTransportRef,
+}
+
+unsafe impl Send for PjSipTransport {}
+unsafe impl Sync for PjSipTransport {}
+
+impl Deref for PjSipTransport {
+    type Target = PjSipTransportRef;
+
+    fn deref(&self) -> &Self::Target {
+        &self.pjsip_transport
+    }
+}
+
+impl From<&PjSipTransportRef> for PjSipTransport {
+    fn from(value: &PjSipTransportRef) -> Self {
+        unsafe { pj::pjsip_transport_add_ref(value.as_mut_ptr()) };
+
+        Self {
+            pjsip_transport: value.as_mut_ptr().into(),
+        }
+    }
+}
+
+impl Clone for PjSipTransport {
+    fn clone(&self) -> Self {
+        Self::from(&self.pjsip_transport)
+    }
+}
+
+impl Drop for PjSipTransport {
+    fn drop(&mut self) {
+        unsafe { pj::pjsip_transport_dec_ref(self.as_mut_ptr()) };
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum PjSipTransportType {
+    Unspecified,
+    Udp,
+    Tcp,
+    Tls,
+    Loop,
+    LoopDgram,
+    Udp6,
+    Tcp6,
+    Tls6,
+}
+
+impl From<u8> for PjSipTransportType {
+    fn from(value: u8) -> Self {
+        match value as pj::pjsip_transport_type_e {
+            pj::pjsip_transport_type_e_PJSIP_TRANSPORT_UDP => Self::Udp,
+            pj::pjsip_transport_type_e_PJSIP_TRANSPORT_TCP => Self::Tcp,
+            pj::pjsip_transport_type_e_PJSIP_TRANSPORT_TLS => Self::Tls,
+            pj::pjsip_transport_type_e_PJSIP_TRANSPORT_LOOP => Self::Loop,
+            pj::pjsip_transport_type_e_PJSIP_TRANSPORT_LOOP_DGRAM => Self::LoopDgram,
+            pj::pjsip_transport_type_e_PJSIP_TRANSPORT_UDP6 => Self::Udp6,
+            pj::pjsip_transport_type_e_PJSIP_TRANSPORT_TCP6 => Self::Tcp6,
+            pj::pjsip_transport_type_e_PJSIP_TRANSPORT_TLS6 => Self::Tls6,
+            _ => Self::Unspecified,
+        }
+    }
+}
+
+impl From<PjSipTransportType> for pj::pjsip_transport_type_e {
+    fn from(value: PjSipTransportType) -> Self {
+        match value {
+            PjSipTransportType::Unspecified => {
+                pj::pjsip_transport_type_e_PJSIP_TRANSPORT_UNSPECIFIED
+            }
+            PjSipTransportType::Udp => pj::pjsip_transport_type_e_PJSIP_TRANSPORT_UDP,
+            PjSipTransportType::Tcp => pj::pjsip_transport_type_e_PJSIP_TRANSPORT_TCP,
+            PjSipTransportType::Tls => pj::pjsip_transport_type_e_PJSIP_TRANSPORT_TLS,
+            PjSipTransportType::Loop => pj::pjsip_transport_type_e_PJSIP_TRANSPORT_LOOP,
+            PjSipTransportType::LoopDgram => pj::pjsip_transport_type_e_PJSIP_TRANSPORT_LOOP_DGRAM,
+            PjSipTransportType::Udp6 => pj::pjsip_transport_type_e_PJSIP_TRANSPORT_UDP6,
+            PjSipTransportType::Tcp6 => pj::pjsip_transport_type_e_PJSIP_TRANSPORT_TCP6,
+            PjSipTransportType::Tls6 => pj::pjsip_transport_type_e_PJSIP_TRANSPORT_TLS6,
+        }
+    }
+}
+
+impl Display for PjSipTransportType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                PjSipTransportType::Unspecified => "Unspecified",
+                PjSipTransportType::Udp => "UDP",
+                PjSipTransportType::Tcp => "TCP",
+                PjSipTransportType::Tls => "TLS",
+                PjSipTransportType::Loop => "LOOP",
+                PjSipTransportType::LoopDgram => "LOOP-DGRAM",
+                PjSipTransportType::Udp6 => "UDP6",
+                PjSipTransportType::Tcp6 => "TCP6",
+                PjSipTransportType::Tls6 => "TLS6",
+            }
+        )
+    }
+}
+
+impl Debug for PjSipTransportType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{self}[{}]", *self as u8)
+    }
+}
+
+/** Snapshot of a transport, see `PjSipEndpoint::transports`. */
+#[derive(Debug)]
+pub struct PjSipTransportInfo {
+    pub transport_type: PjSipTransportType,
+    pub info: String,
+    pub local_addr: String,
+    pub local_name: PjSipHostPort,
+    pub remote_name: PjSipHostPort,
+    pub dir: PjSipTransportDir,
+    pub ref_count: i64,
+}
+
+impl From<&PjSipTransportRef> for PjSipTransportInfo {
+    fn from(value: &PjSipTransportRef) -> Self {
+        Self {
+            transport_type: value.transport_type(),
+            info: value.info().to_string_lossy().into_owned(),
+            local_addr: value.local_addr().to_string(),
+            local_name: value.local_name().to_owned(),
+            remote_name: value.remote_name().to_owned(),
+            dir: value.dir(),
+            ref_count: value.ref_count(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum PjSipTransportState {
+    Connected,
+    Disconnected,
+    Shutdown,
+    Destroy,
+    Unknown,
+}
+
+impl From<u8> for PjSipTransportState {
+    fn from(value: u8) -> Self {
+        match value as pj::pjsip_transport_state {
+            pj::pjsip_transport_state_PJSIP_TP_STATE_CONNECTED => Self::Connected,
+            pj::pjsip_transport_state_PJSIP_TP_STATE_DISCONNECTED => Self::Disconnected,
+            pj::pjsip_transport_state_PJSIP_TP_STATE_SHUTDOWN => Self::Shutdown,
+            pj::pjsip_transport_state_PJSIP_TP_STATE_DESTROY => Self::Destroy,
+            _ => Self::Unknown,
+        }
+    }
+}
+
+impl Display for PjSipTransportState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                PjSipTransportState::Connected => "Connected",
+                PjSipTransportState::Disconnected => "Disconnected",
+                PjSipTransportState::Shutdown => "Shutdown",
+                PjSipTransportState::Destroy => "Destroy",
+                PjSipTransportState::Unknown => "Unknown",
+            }
+        )
+    }
+}
+
+impl Debug for PjSipTransportState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{self}[{}]", *self as u8)
+    }
+}
+
+/** Pins messages to a transport or listener instead of letting pjsip pick
+ * one from the destination. */
+pub struct PjSipTpSelector {
+    pjsip_tpselector: pj::pjsip_tpselector,
+}
+
+impl PjSipTpSelector {
+    /** Send over this transport, e.g. the UDP transport of one interface
+     * of a multi-homed host. */
+    pub fn transport(transport: &PjSipTransportRef) -> Self {
+        let mut pjsip_tpselector = unsafe { std::mem::zeroed::<pj::pjsip_tpselector>() };
+        pjsip_tpselector.type_ = pj::pjsip_tpselector_type_PJSIP_TPSELECTOR_TRANSPORT;
+        pjsip_tpselector.u.transport = transport.as_mut_ptr();
+
+        Self { pjsip_tpselector }
+    }
+
+    /** Send over a connection of this listener, a `PjSipTransportTcp` or
+     * `PjSipTransportTls`. */
+    pub fn listener<L: PjSipListener>(listener: &L) -> Self {
+        let mut pjsip_tpselector = unsafe { std::mem::zeroed::<pj::pjsip_tpselector>() };
+        pjsip_tpselector.type_ = pj::pjsip_tpselector_type_PJSIP_TPSELECTOR_LISTENER;
+        pjsip_tpselector.u.listener = listener.tpfactory() as *mut _;
+
+        Self { pjsip_tpselector }
+    }
+
+    /** Open a new connection rather than reusing one to the same
+     * destination. */
+    pub fn with_disable_connection_reuse(&mut self, disable: bool) -> &mut Self {
+        self.pjsip_tpselector.disable_connection_reuse = disable as _;
+
+        self
+    }
+
+    pub fn as_ptr(&self) -> *const pj::pjsip_tpselector {
+        &self.pjsip_tpselector
+    }
+}
+
+/** Listener of a connection oriented transport, which a selector can
+ * pick. Only the crate's listeners implement it. */
+pub trait PjSipListener: sealed::Sealed {
+    fn tpfactory(&self) -> *const pj::pjsip_tpfactory;
+}
+
+pub(crate) mod sealed {
+    pub trait Sealed {}
+}
+
+type OnTransportState =
+    Arc<dyn Fn(&PjSipTransportRef, PjSipTransportState, PjStatus) + Send + Sync>;
+
+/* pjsip can't enumerate its transports, so every transport manager keeps
+ * a list of the transports it reported or the crate started. */
+struct TpmgrTransports {
+    tpmgr: usize,
+    transports: Vec<usize>,
+    on_state: Option<OnTransportState>,
+}
+
+static TPMGRS: RwLock<Vec<TpmgrTransports>> = parking_lot::const_rwlock(Vec::new());
+
+unsafe extern "C" fn on_transport_state(
+    tp: *mut pj::pjsip_transport,
+    state: pj::pjsip_transport_state,
+    info: *const pj::pjsip_transport_state_info,
+) {
+    let state = PjSipTransportState::from(state as u8);
+    let on_state = {
+        let mut tpmgrs = TPMGRS.write();
+        let Some(tpmgr) = tpmgrs.iter_mut().find(|t| t.tpmgr == (*tp).tpmgr as usize) else {
+            return;
+        };
+
+        tpmgr.transports.retain(|t| *t != tp as usize);
+        if state != PjSipTransportState::Destroy {
+            tpmgr.transports.push(tp as usize);
+        }
+
+        tpmgr.on_state.clone()
+    };
+
+    if let Some(on_state) = on_state {
+        let status = info
+            .as_ref()
+            .map_or(pj::pj_constants__PJ_SUCCESS as _, |i| i.status);
+        on_state(&tp.into(), state, PjStatus::new(status));
+    }
+}
+
+pub(crate) fn register_tpmgr(tpmgr: *mut pj::pjsip_tpmgr) -> Result<(), Error> {
+    let status = unsafe { pj::pjsip_tpmgr_set_state_cb(tpmgr, Some(on_transport_state)) };
+    PjStatus::result_for_status(status)?;
+
+    TPMGRS.write().push(TpmgrTransports {
+        tpmgr: tpmgr as usize,
+        transports: Vec::new(),
+        on_state: None,
+    });
+
+    Ok(())
+}
+
+pub(crate) fn unregister_tpmgr(tpmgr: *mut pj::pjsip_tpmgr) {
+    TPMGRS.write().retain(|t| t.tpmgr != tpmgr as usize);
+}
+
+/* Connectionless transports never report a state, so they are added when
+ * started. Their destruction is reported. */
+pub(crate) fn track_transport(tp: *mut pj::pjsip_transport) {
+    let mut tpmgrs = TPMGRS.write();
+    if let Some(tpmgr) = tpmgrs
+        .iter_mut()
+        .find(|t| t.tpmgr == unsafe { (*tp).tpmgr } as usize)
+    {
+        tpmgr.transports.retain(|t| *t != tp as usize);
+        tpmgr.transports.push(tp as usize);
+    }
+}
+
+pub(crate) fn tpmgr_transports(tpmgr: *mut pj::pjsip_tpmgr) -> Vec<PjSipTransportInfo> {
+    /* Holding the lock keeps the transports from being destroyed, their
+     * destroy state callback waits for it */
+    let tpmgrs = TPMGRS.read();
+
+    tpmgrs
+        .iter()
+        .find(|t| t.tpmgr == tpmgr as usize)
+        .map(|t| {
+            t.transports
+                .iter()
+                .map(|tp| PjSipTransportInfo::from(&PjSipTransportRef::from(*tp as *mut _)))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+pub(crate) fn set_tpmgr_state_cb(tpmgr: *mut pj::pjsip_tpmgr, on_state: Option<OnTransportState>) {
+    if let Some(tpmgr) = TPMGRS
+        .write()
+        .iter_mut()
+        .find(|t| t.tpmgr == tpmgr as usize)
+    {
+        tpmgr.on_state = on_state;
+    }
+}
+
+/** Whether a connection oriented transport was opened by us or accepted
+ * from a peer. */
+#[derive(Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum PjSipTransportDir {
+    None,
+    Outgoing,
+    Incoming,
+}
+
+impl From<u8> for PjSipTransportDir {
+    fn from(value: u8) -> Self {
+        match value as pj::pjsip_transport_dir {
+            pj::pjsip_transport_dir_PJSIP_TP_DIR_OUTGOING => Self::Outgoing,
+            pj::pjsip_transport_dir_PJSIP_TP_DIR_INCOMING => Self::Incoming,
+            _ => Self::None,
+        }
+    }
+}
+
+impl Display for PjSipTransportDir {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                PjSipTransportDir::None => "None",
+                PjSipTransportDir::Outgoing => "Outgoing",
+                PjSipTransportDir::Incoming => "Incoming",
+            }
+        )
+    }
+}
+
+impl Debug for PjSipTransportDir {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{self}[{}]", *self as u8)
+    }
+}
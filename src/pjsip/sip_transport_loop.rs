@@ -0,0 +1,89 @@
+use std::time::Duration;
+
+use pjproject_sys as pj;
+
+use crate::{track_transport, Error, PjSipTransportRef, PjStatus};
+
+use super::PjSipEndpoint;
+
+/** In-process transport that hands every message it sends back to the
+ * same endpoint, without any socket. A UAC and a UAS on one endpoint can
+ * talk over it by addressing requests with `;transport=loop-dgram`. */
+pub struct PjSipTransportLoop {
+    pjsip_transport: *mut pj::pjsip_transport,
+}
+
+unsafe impl Send for PjSipTransportLoop {}
+unsafe impl Sync for PjSipTransportLoop {}
+
+impl PjSipTransportLoop {
+    pub fn new_from_endpoint(endpoint: &PjSipEndpoint) -> Result<Self, Error> {
+        let mut pjsip_transport = std::ptr::null_mut();
+        let status = unsafe { pj::pjsip_loop_start(endpoint.as_mut_ptr(), &mut pjsip_transport) };
+        PjStatus::result_for_status(status)?;
+        track_transport(pjsip_transport);
+
+        Ok(Self { pjsip_transport })
+    }
+
+    pub fn transport(&self) -> PjSipTransportRef {
+        self.pjsip_transport.into()
+    }
+
+    /** Delay delivery of received messages and the completion of sends,
+     * simulating network latency. */
+    pub fn set_delay(&self, delay: Duration) -> Result<(), Error> {
+        let status = unsafe { pj::pjsip_loop_set_delay(self.pjsip_transport, delay_ms(delay)) };
+
+        PjStatus::result_for_status(status)
+    }
+
+    /** Delay delivery of received messages only. */
+    pub fn set_recv_delay(&self, delay: Duration) -> Result<(), Error> {
+        let status = unsafe {
+            pj::pjsip_loop_set_recv_delay(
+                self.pjsip_transport,
+                delay_ms(delay),
+                std::ptr::null_mut(),
+            )
+        };
+
+        PjStatus::result_for_status(status)
+    }
+
+    /** Delay reporting sends as complete only. */
+    pub fn set_send_callback_delay(&self, delay: Duration) -> Result<(), Error> {
+        let status = unsafe {
+            pj::pjsip_loop_set_send_callback_delay(
+                self.pjsip_transport,
+                delay_ms(delay),
+                std::ptr::null_mut(),
+            )
+        };
+
+        PjStatus::result_for_status(status)
+    }
+
+    /** Make every send fail with an error, as if the network was down. */
+    pub fn set_failure(&self, fail: bool) -> Result<(), Error> {
+        let status = unsafe {
+            pj::pjsip_loop_set_failure(self.pjsip_transport, fail as _, std::ptr::null_mut())
+        };
+
+        PjStatus::result_for_status(status)
+    }
+
+    /** Silently drop every message sent while set, as if it was lost on
+     * the way. Sends still report success. */
+    pub fn set_discard(&self, discard: bool) -> Result<(), Error> {
+        let status = unsafe {
+            pj::pjsip_loop_set_discard(self.pjsip_transport, discard as _, std::ptr::null_mut())
+        };
+
+        PjStatus::result_for_status(status)
+    }
+}
+
+fn delay_ms(delay: Duration) -> u32 {
+    delay.as_millis().min(u32::MAX as _) as _
+}
@@ -0,0 +1,88 @@
+use pjproject_sys as pj;
+
+use crate::{Error, PjSipHostPortRef, PjSipListener, PjSockaddrRef, PjStatus, SockaddrT};
+
+use super::PjSipEndpoint;
+
+/** TCP listener, it accepts incoming connections and creates outgoing
+ * ones. pjsip owns it and destroys it with the endpoint unless it is shut
+ * down before. */
+pub struct PjSipTransportTcp {
+    pjsip_tpfactory: *mut pj::pjsip_tpfactory,
+}
+
+impl PjSipTransportTcp {
+    /** Start listening on `local`, publishing `a_name` in Via and Contact
+     * when set, e.g. the public address behind a NAT. Keep-alives are set
+     * for every listener with
+     * `PjSipEndpoint::set_tcp_keep_alive_interval`. */
+    pub fn new_from_endpoint(
+        endpoint: &PjSipEndpoint,
+        local: &SockaddrT,
+        a_name: Option<&PjSipHostPortRef>,
+        async_cnt: u32,
+    ) -> Result<Self, Error> {
+        let mut cfg = unsafe { std::mem::zeroed::<pj::pjsip_tcp_transport_cfg>() };
+        unsafe {
+            pj::pjsip_tcp_transport_cfg_default(&mut cfg, (*local.as_ptr()).addr.sa_family as _);
+            pj::pj_sockaddr_cp(
+                &mut cfg.bind_addr as *mut _ as *mut _,
+                local.as_ptr() as *const _,
+            );
+        }
+        if let Some(a_name) = a_name {
+            cfg.addr_name = unsafe { *a_name.as_ptr() };
+        }
+        cfg.async_cnt = async_cnt;
+
+        let mut pjsip_tpfactory = std::ptr::null_mut();
+        let status = unsafe {
+            pj::pjsip_tcp_transport_start3(endpoint.as_mut_ptr(), &cfg, &mut pjsip_tpfactory)
+        };
+
+        PjStatus::result_for_status(status).map(|_| PjSipTransportTcp { pjsip_tpfactory })
+    }
+
+    pub fn as_ptr(&self) -> *const pj::pjsip_tpfactory {
+        self.pjsip_tpfactory
+    }
+
+    pub fn as_mut_ptr(&mut self) -> *mut pj::pjsip_tpfactory {
+        self.pjsip_tpfactory
+    }
+
+    pub fn as_ref(&self) -> &pj::pjsip_tpfactory {
+        unsafe { &*self.pjsip_tpfactory }
+    }
+
+    /** Address the listener is bound to. */
+    pub fn local_addr(&self) -> PjSockaddrRef<'_> {
+        (&self.as_ref().local_addr).into()
+    }
+
+    /** Address published in Via and Contact. */
+    pub fn local_name(&self) -> PjSipHostPortRef {
+        (&self.as_ref().addr_name).into()
+    }
+
+    /** Stop accepting connections. Connections already established stay
+     * up until they are no longer used. */
+    pub fn shutdown(mut self) -> Result<(), Error> {
+        let status = unsafe {
+            match (*self.pjsip_tpfactory).destroy {
+                Some(destroy) => destroy(self.as_mut_ptr()),
+                None => pj::pj_constants__PJ_SUCCESS as _,
+            }
+        };
+
+        PjStatus::result_for_status(status)
+    }
+}
+
+impl super::sip_transport::sealed::Sealed for PjSipTransportTcp {}
+
+impl PjSipListener for PjSipTransportTcp {
+    fn tpfactory(&self) -> *const pj::pjsip_tpfactory {
+        self.as_ptr()
+    }
+}
@@ -0,0 +1,474 @@
+use std::{
+    ffi::{CStr, CString},
+    fmt::{Debug, Display},
+    sync::Arc,
+    time::Duration,
+};
+
+use parking_lot::RwLock;
+use pjproject_sys as pj;
+
+use crate::{
+    pj_str_to_string, Error, PjSipHostPortRef, PjSipListener, PjSipTransportDir, PjSockaddrRef,
+    PjStatus, PjTimeVal, SockaddrT,
+};
+
+use super::PjSipEndpoint;
+
+/** TLS protocol version, pass several to `with_protocols` to allow them
+ * all. */
+#[derive(Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum PjSslProto {
+    Tls1,
+    Tls1_1,
+    Tls1_2,
+    Tls1_3,
+}
+
+impl From<PjSslProto> for pj::pj_ssl_sock_proto {
+    fn from(value: PjSslProto) -> Self {
+        match value {
+            PjSslProto::Tls1 => pj::pj_ssl_sock_proto_PJ_SSL_SOCK_PROTO_TLS1,
+            PjSslProto::Tls1_1 => pj::pj_ssl_sock_proto_PJ_SSL_SOCK_PROTO_TLS1_1,
+            PjSslProto::Tls1_2 => pj::pj_ssl_sock_proto_PJ_SSL_SOCK_PROTO_TLS1_2,
+            PjSslProto::Tls1_3 => pj::pj_ssl_sock_proto_PJ_SSL_SOCK_PROTO_TLS1_3,
+        }
+    }
+}
+
+impl Display for PjSslProto {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                PjSslProto::Tls1 => "TLSv1",
+                PjSslProto::Tls1_1 => "TLSv1.1",
+                PjSslProto::Tls1_2 => "TLSv1.2",
+                PjSslProto::Tls1_3 => "TLSv1.3",
+            }
+        )
+    }
+}
+
+impl Debug for PjSslProto {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{self}[{}]", *self as u8)
+    }
+}
+
+/** Certificate of one side of a TLS connection. */
+#[derive(Clone, Debug)]
+pub struct PjSslCertInfo {
+    pub version: u32,
+    pub serial_no: Vec<u8>,
+    pub subject_cn: String,
+    /** Full subject, e.g. `/C=DE/O=Example/CN=sip.example.com`. */
+    pub subject: String,
+    pub issuer_cn: String,
+    pub issuer: String,
+    pub not_before: PjTimeVal,
+    pub not_after: PjTimeVal,
+    /** DNS names, URIs and addresses of the subjectAltName extension. */
+    pub subject_alt_names: Vec<String>,
+    /** The certificate in PEM format. */
+    pub pem: String,
+}
+
+impl PjSslCertInfo {
+    unsafe fn from_ptr(info: *const pj::pj_ssl_cert_info) -> Option<Self> {
+        let info = info.as_ref()?;
+
+        let alt_names = &info.subj_alt_name;
+        let subject_alt_names = if alt_names.entry.is_null() {
+            Vec::new()
+        } else {
+            std::slice::from_raw_parts(alt_names.entry, alt_names.cnt as _)
+                .iter()
+                .map(|entry| pj_str_to_string(&entry.name))
+                .collect()
+        };
+
+        Some(Self {
+            version: info.version,
+            serial_no: info.serial_no.to_vec(),
+            subject_cn: pj_str_to_string(&info.subject.cn),
+            subject: pj_str_to_string(&info.subject.info),
+            issuer_cn: pj_str_to_string(&info.issuer.cn),
+            issuer: pj_str_to_string(&info.issuer.info),
+            not_before: PjTimeVal(info.validity.start),
+            not_after: PjTimeVal(info.validity.end),
+            subject_alt_names,
+            pem: pj_str_to_string(&info.raw),
+        })
+    }
+}
+
+/** What a TLS verify callback gets to decide on a connection. */
+#[derive(Clone, Debug)]
+pub struct PjSipTlsVerifyInfo {
+    pub dir: PjSipTransportDir,
+    pub remote_addr: String,
+    pub local_cert: Option<PjSslCertInfo>,
+    pub remote_cert: Option<PjSslCertInfo>,
+    /** `pj_ssl_cert_verify_flag_t` bits, 0 when OpenSSL accepted the
+     * peer certificate. */
+    pub verify_status: u32,
+}
+
+impl PjSipTlsVerifyInfo {
+    pub fn verified(&self) -> bool {
+        self.verify_status == pj::pj_ssl_cert_verify_flag_t_PJ_SSL_CERT_ESUCCESS
+    }
+}
+
+type OnVerify = Arc<dyn Fn(&PjSipTlsVerifyInfo) -> bool + Send + Sync>;
+
+/* pjsip gives the verify callback no user data, so like modules, every
+ * listener with a callback takes a slot with its own trampoline. */
+const VERIFY_SLOT_COUNT: usize = 8;
+
+#[allow(clippy::declare_interior_mutable_const)]
+const VERIFY_SLOT_INIT: RwLock<Option<OnVerify>> = parking_lot::const_rwlock(None);
+static VERIFY_SLOTS: [RwLock<Option<OnVerify>>; VERIFY_SLOT_COUNT] =
+    [VERIFY_SLOT_INIT; VERIFY_SLOT_COUNT];
+
+type VerifyTrampoline = unsafe extern "C" fn(*const pj::pjsip_tls_on_verify_param) -> pj::pj_bool_t;
+
+static ON_VERIFY: [VerifyTrampoline; VERIFY_SLOT_COUNT] = [
+    on_verify::<0>,
+    on_verify::<1>,
+    on_verify::<2>,
+    on_verify::<3>,
+    on_verify::<4>,
+    on_verify::<5>,
+    on_verify::<6>,
+    on_verify::<7>,
+];
+
+unsafe extern "C" fn on_verify<const N: usize>(
+    param: *const pj::pjsip_tls_on_verify_param,
+) -> pj::pj_bool_t {
+    /* Don't hold the slot lock while the callback runs */
+    let Some(cb) = VERIFY_SLOTS[N].read().clone() else {
+        return pj::pj_constants__PJ_TRUE as _;
+    };
+    let param = &*param;
+
+    let info = PjSipTlsVerifyInfo {
+        dir: (param.tp_dir as u8).into(),
+        remote_addr: PjSockaddrRef::from(param.remote_addr as *const pj::pj_sockaddr).to_string(),
+        local_cert: PjSslCertInfo::from_ptr(param.local_cert_info),
+        remote_cert: PjSslCertInfo::from_ptr(param.remote_cert_info),
+        verify_status: param.verify_status,
+    };
+
+    cb(&info) as _
+}
+
+/** Settings of a TLS transport. Certificates and keys are given either as
+ * files or as PEM data, e.g. the same PEM a rustls configuration is built
+ * from. */
+pub struct PjSipTlsSetting {
+    pjsip_tls_setting: pj::pjsip_tls_setting,
+    /* Owners of the memory the pj_str_t of the setting point into */
+    strings: Vec<CString>,
+    buffers: Vec<Vec<u8>>,
+    ciphers: Vec<pj::pj_ssl_cipher>,
+    on_verify: Option<OnVerify>,
+}
+
+unsafe impl Send for PjSipTlsSetting {}
+unsafe impl Sync for PjSipTlsSetting {}
+
+impl PjSipTlsSetting {
+    pub fn new() -> Self {
+        let mut pjsip_tls_setting = unsafe { std::mem::zeroed::<pj::pjsip_tls_setting>() };
+        unsafe { pj::pjsip_tls_setting_default(&mut pjsip_tls_setting) };
+
+        Self {
+            pjsip_tls_setting,
+            strings: Vec::new(),
+            buffers: Vec::new(),
+            ciphers: Vec::new(),
+            on_verify: None,
+        }
+    }
+
+    fn keep_str<S: AsRef<CStr>>(&mut self, s: S) -> pj::pj_str_t {
+        let s = s.as_ref().to_owned();
+        let pj_str = unsafe { pj::pj_str(s.as_ptr() as *mut _) };
+        self.strings.push(s);
+
+        pj_str
+    }
+
+    fn keep_buf(&mut self, buf: &[u8]) -> pj::pj_str_t {
+        let buf = buf.to_vec();
+        let pj_str = pj::pj_str_t {
+            ptr: buf.as_ptr() as *mut _,
+            slen: buf.len() as _,
+        };
+        self.buffers.push(buf);
+
+        pj_str
+    }
+
+    /** File with the CA certificates to verify peers with. */
+    pub fn with_ca_file<S: AsRef<CStr>>(&mut self, path: S) -> &mut Self {
+        self.pjsip_tls_setting.ca_list_file = self.keep_str(path);
+
+        self
+    }
+
+    /** Directory with hashed CA certificates, e.g. `/etc/ssl/certs`. */
+    pub fn with_ca_path<S: AsRef<CStr>>(&mut self, path: S) -> &mut Self {
+        self.pjsip_tls_setting.ca_list_path = self.keep_str(path);
+
+        self
+    }
+
+    /** CA certificates as PEM data. */
+    pub fn with_ca_pem(&mut self, pem: &[u8]) -> &mut Self {
+        self.pjsip_tls_setting.ca_buf = self.keep_buf(pem);
+
+        self
+    }
+
+    /** Certificate chain and private key files, both PEM. */
+    pub fn with_cert_file<C: AsRef<CStr>, K: AsRef<CStr>>(&mut self, cert: C, key: K) -> &mut Self {
+        self.pjsip_tls_setting.cert_file = self.keep_str(cert);
+        self.pjsip_tls_setting.privkey_file = self.keep_str(key);
+
+        self
+    }
+
+    /** Certificate chain and private key as PEM data. */
+    pub fn with_cert_pem(&mut self, cert: &[u8], key: &[u8]) -> &mut Self {
+        self.pjsip_tls_setting.cert_buf = self.keep_buf(cert);
+        self.pjsip_tls_setting.privkey_buf = self.keep_buf(key);
+
+        self
+    }
+
+    /** Password of an encrypted private key. */
+    pub fn with_key_password<S: AsRef<CStr>>(&mut self, password: S) -> &mut Self {
+        self.pjsip_tls_setting.password = self.keep_str(password);
+
+        self
+    }
+
+    /** Verify the certificate of servers we connect to, connections with
+     * an invalid certificate are closed. */
+    pub fn with_verify_server(&mut self, verify: bool) -> &mut Self {
+        self.pjsip_tls_setting.verify_server = verify as _;
+
+        self
+    }
+
+    /** Verify the certificate of clients connecting to us, connections
+     * with an invalid certificate are closed. */
+    pub fn with_verify_client(&mut self, verify: bool) -> &mut Self {
+        self.pjsip_tls_setting.verify_client = verify as _;
+
+        self
+    }
+
+    /** Reject clients that don't present a certificate. */
+    pub fn with_require_client_cert(&mut self, require: bool) -> &mut Self {
+        self.pjsip_tls_setting.require_client_cert = require as _;
+
+        self
+    }
+
+    /** Server name sent in the SNI extension on outgoing connections. */
+    pub fn with_server_name<S: AsRef<CStr>>(&mut self, server_name: S) -> &mut Self {
+        self.pjsip_tls_setting.server_name = self.keep_str(server_name);
+
+        self
+    }
+
+    /** Protocol versions to allow, all the backend supports by default. */
+    pub fn with_protocols(&mut self, protocols: &[PjSslProto]) -> &mut Self {
+        self.pjsip_tls_setting.proto = protocols
+            .iter()
+            .fold(0, |proto, p| proto | pj::pj_ssl_sock_proto::from(*p));
+
+        self
+    }
+
+    /** Cipher suites to offer by their OpenSSL name, e.g.
+     * `ECDHE-RSA-AES256-GCM-SHA384`. Fails on a name the backend does not
+     * know. */
+    pub fn with_ciphers(&mut self, ciphers: &[&CStr]) -> Result<&mut Self, Error> {
+        self.ciphers = ciphers
+            .iter()
+            .map(|name| {
+                let id = unsafe { pj::pj_ssl_cipher_id(name.as_ptr()) };
+                /* PJ_TLS_UNKNOWN_CIPHER */
+                if id as i32 == -1 {
+                    return Err(Error::Validation(format!(
+                        "Unknown TLS cipher {}",
+                        name.to_string_lossy()
+                    )));
+                }
+
+                Ok(id)
+            })
+            .collect::<Result<_, _>>()?;
+
+        self.pjsip_tls_setting.ciphers_num = self.ciphers.len() as _;
+        self.pjsip_tls_setting.ciphers = self.ciphers.as_mut_ptr();
+
+        Ok(self)
+    }
+
+    /** How long a handshake may take. */
+    pub fn with_handshake_timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.pjsip_tls_setting.timeout = pj::pj_time_val {
+            sec: timeout.as_secs() as _,
+            msec: timeout.subsec_millis() as _,
+        };
+
+        self
+    }
+
+    /** Called for every connection once the handshake is done, with the
+     * certificates and OpenSSL's verdict. Return false to close the
+     * connection. */
+    pub fn with_on_verify<F>(&mut self, cb: F) -> &mut Self
+    where
+        F: Fn(&PjSipTlsVerifyInfo) -> bool + Send + Sync + 'static,
+    {
+        self.on_verify = Some(Arc::new(cb));
+
+        self
+    }
+
+    pub fn as_ptr(&self) -> *const pj::pjsip_tls_setting {
+        &self.pjsip_tls_setting
+    }
+}
+
+impl Default for PjSipTlsSetting {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/** TLS listener, it accepts incoming connections and creates the outgoing
+ * ones `sips:` URIs and `;transport=tls` are sent over. pjsip owns it and
+ * destroys it with the endpoint unless it is shut down before. */
+pub struct PjSipTransportTls {
+    pjsip_tpfactory: *mut pj::pjsip_tpfactory,
+    verify_slot: Option<usize>,
+}
+
+impl PjSipTransportTls {
+    /** Start listening on `local`, or on any address and a random port
+     * when a listener is only needed for outgoing connections. `a_name` is
+     * the address published in Via and Contact when set. */
+    pub fn new_from_endpoint(
+        endpoint: &PjSipEndpoint,
+        setting: &PjSipTlsSetting,
+        local: Option<&SockaddrT>,
+        a_name: Option<&PjSipHostPortRef>,
+        async_cnt: u32,
+    ) -> Result<Self, Error> {
+        /* pjsip copies the setting, the trampoline is only set on the copy
+         * it gets */
+        let mut opt = setting.pjsip_tls_setting;
+
+        let verify_slot = match setting.on_verify.as_ref() {
+            Some(cb) => {
+                let slot = VERIFY_SLOTS
+                    .iter()
+                    .position(|slot| {
+                        let mut slot = slot.write();
+                        if slot.is_some() {
+                            return false;
+                        }
+                        slot.replace(cb.clone());
+
+                        true
+                    })
+                    .ok_or_else(|| Error::Validation("No free TLS verify slots".into()))?;
+                opt.on_verify_cb = Some(ON_VERIFY[slot]);
+
+                Some(slot)
+            }
+            None => None,
+        };
+
+        let mut pjsip_tpfactory = std::ptr::null_mut();
+        let status = unsafe {
+            pj::pjsip_tls_transport_start2(
+                endpoint.as_mut_ptr(),
+                &opt,
+                local.map_or(std::ptr::null(), |local| local.as_ptr()),
+                a_name.map_or(std::ptr::null(), |a_name| a_name.as_ptr()),
+                async_cnt,
+                &mut pjsip_tpfactory,
+            )
+        };
+
+        PjStatus::result_for_status(status)
+            .map(|_| PjSipTransportTls {
+                pjsip_tpfactory,
+                verify_slot,
+            })
+            .inspect_err(|_| {
+                if let Some(slot) = verify_slot {
+                    VERIFY_SLOTS[slot].write().take();
+                }
+            })
+    }
+
+    pub fn as_ptr(&self) -> *const pj::pjsip_tpfactory {
+        self.pjsip_tpfactory
+    }
+
+    pub fn as_mut_ptr(&mut self) -> *mut pj::pjsip_tpfactory {
+        self.pjsip_tpfactory
+    }
+
+    pub fn as_ref(&self) -> &pj::pjsip_tpfactory {
+        unsafe { &*self.pjsip_tpfactory }
+    }
+
+    /** Address the listener is bound to. */
+    pub fn local_addr(&self) -> PjSockaddrRef<'_> {
+        (&self.as_ref().local_addr).into()
+    }
+
+    /** Address published in Via and Contact. */
+    pub fn local_name(&self) -> PjSipHostPortRef {
+        (&self.as_ref().addr_name).into()
+    }
+
+    /** Stop accepting connections and release the verify callback.
+     * Connections already established stay up until they are no longer
+     * used. */
+    pub fn shutdown(mut self) -> Result<(), Error> {
+        let status = unsafe {
+            match (*self.pjsip_tpfactory).destroy {
+                Some(destroy) => destroy(self.as_mut_ptr()),
+                None => pj::pj_constants__PJ_SUCCESS as _,
+            }
+        };
+        if let Some(slot) = self.verify_slot {
+            VERIFY_SLOTS[slot].write().take();
+        }
+
+        PjStatus::result_for_status(status)
+    }
+}
+
+impl super::sip_transport::sealed::Sealed for PjSipTransportTls {}
+
+impl PjSipListener for PjSipTransportTls {
+    fn tpfactory(&self) -> *const pj::pjsip_tpfactory {
+        self.as_ptr()
+    }
+}
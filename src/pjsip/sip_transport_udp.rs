@@ -1,6 +1,9 @@
 use pjproject_sys as pj;
 
-use crate::{Error, PjSipHostPortRef, PjSockaddrInRef, PjStatus};
+use crate::{
+    track_transport, Error, PjSipHostPortRef, PjSipTransportRef, PjSockaddrRef, PjStatus,
+    SockaddrTRef,
+};
 
 use super::PjSipEndpoint;
 
@@ -9,9 +12,12 @@ pub struct PjSipTransportUdp {
 }
 
 impl PjSipTransportUdp {
+    /** Start a UDP transport on an IPv4 or IPv6 address. An endpoint can
+     * run one of each for dual stack, pjsip picks the transport matching
+     * the family of the destination. */
     pub fn new_from_endpoint(
         endpoint: &PjSipEndpoint,
-        local: &PjSockaddrInRef,
+        local: &SockaddrTRef,
         a_name: Option<&PjSipHostPortRef>,
         async_cnt: u32,
     ) -> Result<Self, Error> {
@@ -22,16 +28,32 @@ impl PjSipTransportUdp {
 
         let mut pjsip_transport = std::ptr::null_mut();
         let status = unsafe {
-            pj::pjsip_udp_transport_start(
-                endpoint.as_mut_ptr(),
-                local.as_ptr(),
-                a_name,
-                async_cnt,
-                &mut pjsip_transport,
-            )
+            match local {
+                SockaddrTRef::IPv4(local) => pj::pjsip_udp_transport_start(
+                    endpoint.as_mut_ptr(),
+                    local.as_ptr(),
+                    a_name,
+                    async_cnt,
+                    &mut pjsip_transport,
+                ),
+                SockaddrTRef::IPv6(local) => pj::pjsip_udp_transport_start6(
+                    endpoint.as_mut_ptr(),
+                    local.as_ptr(),
+                    a_name,
+                    async_cnt,
+                    &mut pjsip_transport,
+                ),
+            }
         };
 
-        PjStatus::result_for_status(status).map(|_| PjSipTransportUdp { pjsip_transport })
+        PjStatus::result_for_status(status)?;
+        track_transport(pjsip_transport);
+
+        Ok(PjSipTransportUdp { pjsip_transport })
+    }
+
+    pub fn transport(&self) -> PjSipTransportRef {
+        (self.pjsip_transport as *mut pj::pjsip_transport).into()
     }
 
     pub fn as_ref(&self) -> &pj::pjsip_transport {
@@ -41,4 +63,13 @@ impl PjSipTransportUdp {
     pub fn local_name(&self) -> PjSipHostPortRef {
         (&self.as_ref().local_name).into()
     }
+
+    /** Address the transport is bound to. */
+    pub fn local_addr(&self) -> PjSockaddrRef<'_> {
+        (&self.as_ref().local_addr).into()
+    }
+
+    pub fn is_ipv6(&self) -> bool {
+        unsafe { self.as_ref().local_addr.addr.sa_family == *crate::PJ_AF_INET6 }
+    }
 }
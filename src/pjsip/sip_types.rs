@@ -170,3 +170,40 @@ impl ToOwned for PjSipHostPortRef {
         Self::Owned::new(host, port)
     }
 }
+
+/** Whether we sent (UAC) or received (UAS) the request that created a
+ * transaction, dialog or session. */
+#[derive(Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum PjSipRole {
+    Uac,
+    Uas,
+}
+
+impl From<u8> for PjSipRole {
+    fn from(value: u8) -> Self {
+        match value as pj::pjsip_role_e {
+            pj::pjsip_role_e_PJSIP_ROLE_UAS => Self::Uas,
+            _ => Self::Uac,
+        }
+    }
+}
+
+impl Display for PjSipRole {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                PjSipRole::Uac => "UAC",
+                PjSipRole::Uas => "UAS",
+            }
+        )
+    }
+}
+
+impl Debug for PjSipRole {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{self}[{}]", *self as u8)
+    }
+}
@@ -0,0 +1,690 @@
+use std::{
+    ffi::{CStr, CString},
+    fmt::{Debug, Display},
+    os::raw::c_char,
+};
+
+use pjproject_sys as pj;
+
+use crate::{pj_str_to_string, Error, PjPool};
+
+/** View of a URI owned by a pjsip message or pool. */
+#[repr(transparent)]
+pub struct PjSipUriRef {
+    pjsip_uri: *const pj::pjsip_uri,
+}
+
+impl PjSipUriRef {
+    /* View the URI pointer stored in `uri`, e.g. a field of a header,
+     * borrowed as long as the object holding it */
+    pub(crate) unsafe fn from_field(uri: &*mut pj::pjsip_uri) -> &Self {
+        &*(uri as *const *mut pj::pjsip_uri as *const Self)
+    }
+
+    pub fn as_ptr(&self) -> *const pj::pjsip_uri {
+        self.pjsip_uri
+    }
+
+    pub fn as_ref(&self) -> &pj::pjsip_uri {
+        unsafe { &*self.as_ptr() }
+    }
+
+    /** Scheme, e.g. `sip`, `sips` or `tel`. */
+    pub fn scheme(&self) -> String {
+        unsafe {
+            match (*self.as_ref().vptr).p_get_scheme {
+                Some(p_get_scheme) => pj_str_to_string(&*p_get_scheme(self.pjsip_uri as *const _)),
+                None => String::new(),
+            }
+        }
+    }
+
+    /** The URI inside a name-addr, or this URI itself. */
+    pub fn uri(&self) -> PjSipUriRef {
+        unsafe {
+            match (*self.as_ref().vptr).p_get_uri {
+                Some(p_get_uri) => {
+                    PjSipUriRef::from(p_get_uri(self.pjsip_uri as *mut _) as *const pj::pjsip_uri)
+                }
+                None => PjSipUriRef::from(self.pjsip_uri),
+            }
+        }
+    }
+
+    /* Fields of a sip: or sips: URI, None for other schemes */
+    fn sip_uri(&self) -> Option<&pj::pjsip_sip_uri> {
+        let uri = self.uri();
+        matches!(uri.scheme().as_str(), "sip" | "sips")
+            .then(|| unsafe { &*(uri.as_ptr() as *const pj::pjsip_sip_uri) })
+    }
+
+    pub fn is_secure(&self) -> bool {
+        self.uri().scheme() == "sips"
+    }
+
+    pub fn user(&self) -> Option<String> {
+        self.sip_uri()
+            .map(|uri| pj_str_to_string(&uri.user))
+            .filter(|user| !user.is_empty())
+    }
+
+    pub fn host(&self) -> Option<String> {
+        self.sip_uri().map(|uri| pj_str_to_string(&uri.host))
+    }
+
+    /** Port, None when the URI has none and the default applies. */
+    pub fn port(&self) -> Option<u16> {
+        self.sip_uri()
+            .map(|uri| uri.port as u16)
+            .filter(|port| *port != 0)
+    }
+
+    /** Value of the `transport` parameter, e.g. `tcp`. */
+    pub fn transport_param(&self) -> Option<String> {
+        self.sip_uri()
+            .map(|uri| pj_str_to_string(&uri.transport_param))
+            .filter(|transport| !transport.is_empty())
+    }
+
+    /** Value of a URI parameter pjsip has no field for, e.g. `ob`. A
+     * parameter without value gives an empty string. */
+    pub fn param<S: AsRef<CStr>>(&self, name: S) -> Option<String> {
+        let uri = self.sip_uri()?;
+        let name = unsafe { pj::pj_str(name.as_ref().as_ptr() as *mut _) };
+        let param = unsafe { pj::pjsip_param_find(&uri.other_param, &name) };
+
+        (!param.is_null()).then(|| pj_str_to_string(unsafe { &(*param).value }))
+    }
+
+    /** Parameters pjsip has no field for, in order. */
+    pub fn params(&self) -> Vec<(String, String)> {
+        self.sip_uri()
+            .map(|uri| param_list(&uri.other_param))
+            .unwrap_or_default()
+    }
+
+    /** Headers of the URI, e.g. `Subject` in `sip:bob@example.com?Subject=hi`. */
+    pub fn headers(&self) -> Vec<(String, String)> {
+        self.sip_uri()
+            .map(|uri| param_list(&uri.header_param))
+            .unwrap_or_default()
+    }
+
+    /** The URI as text, ready for `CStr` arguments. */
+    pub fn to_cstring(&self) -> CString {
+        CString::new(self.to_string()).expect("printed URI has no NUL bytes")
+    }
+
+    /** Print the URI as it would appear in the given header context. */
+    pub fn print(&self, context: pj::pjsip_uri_context_e) -> String {
+        let p_print = match unsafe { (*self.as_ref().vptr).p_print } {
+            Some(p_print) => p_print,
+            None => return String::new(),
+        };
+
+        let mut buf = vec![0 as c_char; 256];
+        loop {
+            let len = unsafe {
+                p_print(
+                    context,
+                    self.pjsip_uri as *const _,
+                    buf.as_mut_ptr(),
+                    buf.len() as _,
+                )
+            };
+
+            /* pjsip returns -1 when the buffer is too small */
+            if len < 0 && buf.len() < 65536 {
+                buf.resize(buf.len() * 2, 0);
+                continue;
+            }
+
+            buf.truncate(len.max(0) as usize);
+            buf.push(0);
+            return unsafe { CStr::from_ptr(buf.as_ptr()) }
+                .to_string_lossy()
+                .into_owned();
+        }
+    }
+}
+
+/* Collect a pjsip_param list, the head is a sentinel */
+fn param_list(head: &pj::pjsip_param) -> Vec<(String, String)> {
+    let mut params = Vec::new();
+    let mut param = head.next as *const pj::pjsip_param;
+    while !std::ptr::eq(param, head) {
+        let p = unsafe { &*param };
+        params.push((pj_str_to_string(&p.name), pj_str_to_string(&p.value)));
+        param = p.next;
+    }
+
+    params
+}
+
+/* Compare URIs as RFC 3261 19.1.4 says, e.g. ignoring the case of the
+ * host and the order of parameters */
+fn uri_eq(a: &PjSipUriRef, b: &PjSipUriRef) -> bool {
+    unsafe {
+        match (*a.as_ref().vptr).p_compare {
+            Some(p_compare) => {
+                a.as_ref().vptr == b.as_ref().vptr
+                    && p_compare(
+                        pj::pjsip_uri_context_e_PJSIP_URI_IN_OTHER,
+                        a.as_ptr() as *const _,
+                        b.as_ptr() as *const _,
+                    ) == pj::pj_constants__PJ_SUCCESS as _
+            }
+            None => false,
+        }
+    }
+}
+
+/* Parse `uri` into `pool`. The parsed URI points into the text, so it is
+ * copied to the pool first. */
+fn parse_uri(pool: &mut PjPool, uri: &CStr, options: u32) -> Result<*mut pj::pjsip_uri, Error> {
+    let parsed = unsafe {
+        let mut text = std::mem::zeroed::<pj::pj_str_t>();
+        pj::pj_strdup2_with_null(pool.as_mut_ptr(), &mut text, uri.as_ptr());
+
+        pj::pjsip_parse_uri(pool.as_mut_ptr(), text.ptr, text.slen as _, options as _)
+    };
+    if parsed.is_null() {
+        return Err(Error::Validation(format!(
+            "Invalid SIP URI {}",
+            uri.to_string_lossy()
+        )));
+    }
+
+    Ok(parsed)
+}
+
+/* Copy `uri` into `pool`, as pjsip_uri_clone does */
+fn clone_uri(pool: &mut PjPool, uri: &PjSipUriRef) -> *mut pj::pjsip_uri {
+    unsafe {
+        let p_clone = (*uri.as_ref().vptr)
+            .p_clone
+            .expect("pjsip URIs can be cloned");
+
+        p_clone(pool.as_mut_ptr(), uri.as_ptr() as *const _) as *mut _
+    }
+}
+
+/* A host is a domain name or an IPv4 or IPv6 address, nothing that would
+ * end it early, e.g. `;`, `@` or `?` */
+fn check_host(host: &str) -> Result<(), Error> {
+    let valid = !host.is_empty()
+        && host
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b"-.:[]".contains(&b));
+    if !valid {
+        return Err(Error::Validation(format!("Invalid host {host}")));
+    }
+
+    Ok(())
+}
+
+fn uri_pool() -> PjPool {
+    PjPool::default_with_name(c"sip_uri")
+}
+
+impl From<*const pj::pjsip_uri> for PjSipUriRef {
+    fn from(value: *const pj::pjsip_uri) -> Self {
+        Self { pjsip_uri: value }
+    }
+}
+
+impl From<*mut pj::pjsip_uri> for PjSipUriRef {
+    fn from(value: *mut pj::pjsip_uri) -> Self {
+        Self { pjsip_uri: value }
+    }
+}
+
+impl Display for PjSipUriRef {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            self.print(pj::pjsip_uri_context_e_PJSIP_URI_IN_OTHER)
+        )
+    }
+}
+
+impl Debug for PjSipUriRef {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "PjSipUriRef({self})")
+    }
+}
+
+/** A parsed URI, e.g. `sip:alice@example.com;transport=tcp` or
+ * `tel:+15551234567`, in its own pool. */
+pub struct PjSipUri {
+    pjsip_uri: *mut pj::pjsip_uri,
+    pool: PjPool,
+}
+
+unsafe impl Send for PjSipUri {}
+unsafe impl Sync for PjSipUri {}
+
+impl PjSipUri {
+    /** Parse a URI. A name-addr, e.g. `"Alice" <sip:alice@example.com>`,
+     * is accepted and its URI kept. */
+    pub fn parse<S: AsRef<CStr>>(uri: S) -> Result<Self, Error> {
+        let mut pool = uri_pool();
+        let parsed = parse_uri(&mut pool, uri.as_ref(), 0)?;
+
+        Ok(Self {
+            pjsip_uri: PjSipUriRef::from(parsed).uri().as_ptr() as *mut _,
+            pool,
+        })
+    }
+
+    pub fn builder<S: AsRef<CStr>>(host: S) -> PjSipUriBuilder {
+        PjSipUriBuilder::new(host)
+    }
+
+    pub fn as_ptr(&self) -> *const pj::pjsip_uri {
+        self.pjsip_uri
+    }
+
+    pub fn as_uri_ref(&self) -> PjSipUriRef {
+        PjSipUriRef::from(self.pjsip_uri)
+    }
+
+    fn sip_uri_mut(&mut self) -> Result<&mut pj::pjsip_sip_uri, Error> {
+        match self.as_uri_ref().sip_uri() {
+            Some(_) => Ok(unsafe { &mut *(self.pjsip_uri as *mut pj::pjsip_sip_uri) }),
+            None => Err(Error::Validation("Not a sip: or sips: URI".into())),
+        }
+    }
+
+    /** Replace the user part, e.g. to normalise a number to E.164. It is
+     * given unescaped, pjsip escapes it when printing. */
+    pub fn set_user<S: AsRef<CStr>>(&mut self, user: S) -> Result<(), Error> {
+        self.set_checked(|uri| &mut uri.user, user.as_ref())
+    }
+
+    /** Replace the host, a domain name or an IPv4 or IPv6 address. */
+    pub fn set_host<S: AsRef<CStr>>(&mut self, host: S) -> Result<(), Error> {
+        check_host(&host.as_ref().to_string_lossy())?;
+
+        self.set_checked(|uri| &mut uri.host, host.as_ref())
+    }
+
+    /* Set a part of the URI, keeping the old value unless the URI still
+     * prints as one that parses */
+    fn set_checked<F>(&mut self, part: F, value: &CStr) -> Result<(), Error>
+    where
+        F: Fn(&mut pj::pjsip_sip_uri) -> &mut pj::pj_str_t,
+    {
+        let pool = self.pool.as_mut_ptr();
+        let old = *part(self.sip_uri_mut()?);
+        unsafe { pj::pj_strdup2_with_null(pool, part(self.sip_uri_mut()?), value.as_ptr()) };
+
+        if let Err(err) = PjSipUri::parse(self.to_cstring()) {
+            *part(self.sip_uri_mut()?) = old;
+            return Err(err);
+        }
+
+        Ok(())
+    }
+
+    /** Set the port, None removes it so the default applies. */
+    pub fn set_port(&mut self, port: Option<u16>) -> Result<(), Error> {
+        self.sip_uri_mut()?.port = port.unwrap_or(0) as _;
+
+        Ok(())
+    }
+}
+
+impl std::ops::Deref for PjSipUri {
+    type Target = PjSipUriRef;
+
+    fn deref(&self) -> &Self::Target {
+        /* PjSipUriRef is a transparent wrapper of the pointer */
+        unsafe { &*(&self.pjsip_uri as *const *mut pj::pjsip_uri as *const PjSipUriRef) }
+    }
+}
+
+impl std::str::FromStr for PjSipUri {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse(CString::new(s)?)
+    }
+}
+
+impl Clone for PjSipUri {
+    fn clone(&self) -> Self {
+        let mut pool = uri_pool();
+        let pjsip_uri = clone_uri(&mut pool, &self.as_uri_ref());
+
+        Self { pjsip_uri, pool }
+    }
+}
+
+impl PartialEq for PjSipUri {
+    fn eq(&self, other: &Self) -> bool {
+        uri_eq(&self.as_uri_ref(), &other.as_uri_ref())
+    }
+}
+
+impl Display for PjSipUri {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_uri_ref())
+    }
+}
+
+impl Debug for PjSipUri {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "PjSipUri({self})")
+    }
+}
+
+/** Builds a `sip:` or `sips:` URI, the result is checked by parsing it.
+ * The user, parameters and headers are given unescaped, characters not
+ * allowed where they go are escaped. */
+pub struct PjSipUriBuilder {
+    secure: bool,
+    user: Option<String>,
+    host: String,
+    port: Option<u16>,
+    params: Vec<(String, Option<String>)>,
+    headers: Vec<(String, String)>,
+}
+
+impl PjSipUriBuilder {
+    pub fn new<S: AsRef<CStr>>(host: S) -> Self {
+        Self {
+            secure: false,
+            user: None,
+            host: host.as_ref().to_string_lossy().into_owned(),
+            port: None,
+            params: Vec::new(),
+            headers: Vec::new(),
+        }
+    }
+
+    /** Build a `sips:` URI. */
+    pub fn with_secure(&mut self, secure: bool) -> &mut Self {
+        self.secure = secure;
+
+        self
+    }
+
+    pub fn with_user<S: AsRef<CStr>>(&mut self, user: S) -> &mut Self {
+        self.user = Some(user.as_ref().to_string_lossy().into_owned());
+
+        self
+    }
+
+    pub fn with_port(&mut self, port: u16) -> &mut Self {
+        self.port = Some(port);
+
+        self
+    }
+
+    pub fn with_transport<S: AsRef<CStr>>(&mut self, transport: S) -> &mut Self {
+        self.with_param(c"transport", Some(transport))
+    }
+
+    /** Add a URI parameter, e.g. `lr` without a value. */
+    pub fn with_param<S: AsRef<CStr>, V: AsRef<CStr>>(
+        &mut self,
+        name: S,
+        value: Option<V>,
+    ) -> &mut Self {
+        self.params.push((
+            name.as_ref().to_string_lossy().into_owned(),
+            value.map(|v| v.as_ref().to_string_lossy().into_owned()),
+        ));
+
+        self
+    }
+
+    pub fn with_header<S: AsRef<CStr>, V: AsRef<CStr>>(&mut self, name: S, value: V) -> &mut Self {
+        self.headers.push((
+            name.as_ref().to_string_lossy().into_owned(),
+            value.as_ref().to_string_lossy().into_owned(),
+        ));
+
+        self
+    }
+
+    pub fn build(&self) -> Result<PjSipUri, Error> {
+        check_host(&self.host)?;
+
+        let mut uri = String::from(if self.secure { "sips:" } else { "sip:" });
+        if let Some(user) = self.user.as_ref() {
+            uri.push_str(&escape(user, USER_UNRESERVED));
+            uri.push('@');
+        }
+        /* IPv6 references are bracketed */
+        if self.host.contains(':') && !self.host.starts_with('[') {
+            uri.push_str(&format!("[{}]", self.host));
+        } else {
+            uri.push_str(&self.host);
+        }
+        if let Some(port) = self.port {
+            uri.push_str(&format!(":{port}"));
+        }
+        for (name, value) in &self.params {
+            uri.push(';');
+            uri.push_str(&escape(name, PARAM_UNRESERVED));
+            if let Some(value) = value {
+                uri.push('=');
+                uri.push_str(&escape(value, PARAM_UNRESERVED));
+            }
+        }
+        for (i, (name, value)) in self.headers.iter().enumerate() {
+            uri.push(if i == 0 { '?' } else { '&' });
+            uri.push_str(&escape(name, HNV_UNRESERVED));
+            uri.push('=');
+            uri.push_str(&escape(value, HNV_UNRESERVED));
+        }
+
+        PjSipUri::parse(CString::new(uri)?)
+    }
+}
+
+/* Characters allowed unescaped besides the unreserved ones, RFC 3261
+ * section 25.1 */
+const USER_UNRESERVED: &str = "&=+$,;?/";
+const PARAM_UNRESERVED: &str = "[]/:&+$";
+const HNV_UNRESERVED: &str = "[]/?:+$";
+
+/* Percent-encode what is neither unreserved nor in `allowed` */
+fn escape(s: &str, allowed: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for b in s.bytes() {
+        if b.is_ascii_alphanumeric() || b"-_.!~*'()".contains(&b) || allowed.as_bytes().contains(&b)
+        {
+            escaped.push(b as char);
+        } else {
+            escaped.push_str(&format!("%{b:02X}"));
+        }
+    }
+
+    escaped
+}
+
+/* Escape quotes and backslashes as quoted-pairs, for a quoted string */
+pub(crate) fn quote(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+fn unquote(s: &str) -> String {
+    let mut unquoted = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => unquoted.extend(chars.next()),
+            c => unquoted.push(c),
+        }
+    }
+
+    unquoted
+}
+
+/** A URI with an optional display name, as used in From, To and Contact,
+ * e.g. `"Alice" <sip:alice@example.com>`. */
+pub struct PjSipNameAddr {
+    pjsip_name_addr: *mut pj::pjsip_name_addr,
+    pool: PjPool,
+}
+
+unsafe impl Send for PjSipNameAddr {}
+unsafe impl Sync for PjSipNameAddr {}
+
+impl PjSipNameAddr {
+    /** Parse a name-addr, a bare URI is accepted too. */
+    pub fn parse<S: AsRef<CStr>>(name_addr: S) -> Result<Self, Error> {
+        let mut pool = uri_pool();
+        let parsed = parse_uri(
+            &mut pool,
+            name_addr.as_ref(),
+            pj::PJSIP_PARSE_URI_AS_NAMEADDR,
+        )?;
+
+        Ok(Self {
+            pjsip_name_addr: parsed as *mut _,
+            pool,
+        })
+    }
+
+    pub fn new<S: AsRef<CStr>>(display: Option<S>, uri: &PjSipUri) -> Result<Self, Error> {
+        let name_addr = match display {
+            Some(display) => {
+                let display = quote(&display.as_ref().to_string_lossy());
+                format!("\"{display}\" <{uri}>")
+            }
+            None => format!("<{uri}>"),
+        };
+
+        Self::parse(CString::new(name_addr)?)
+    }
+
+    pub fn as_ptr(&self) -> *const pj::pjsip_name_addr {
+        self.pjsip_name_addr
+    }
+
+    /** The display name, without the quoting. */
+    pub fn display(&self) -> Option<String> {
+        /* pjsip keeps the quoted-pairs of a quoted display name */
+        let display = pj_str_to_string(unsafe { &(*self.pjsip_name_addr).display });
+
+        (!display.is_empty()).then(|| unquote(&display))
+    }
+
+    pub fn uri(&self) -> PjSipUriRef {
+        PjSipUriRef::from(unsafe { (*self.pjsip_name_addr).uri })
+    }
+
+    pub fn set_display<S: AsRef<CStr>>(&mut self, display: S) {
+        /* Printed in quotes as is */
+        let display = CString::new(quote(&display.as_ref().to_string_lossy()))
+            .expect("quoted display name has no NUL bytes");
+        unsafe {
+            pj::pj_strdup2_with_null(
+                self.pool.as_mut_ptr(),
+                &mut (*self.pjsip_name_addr).display,
+                display.as_ptr(),
+            )
+        };
+    }
+
+    pub fn to_cstring(&self) -> CString {
+        CString::new(self.to_string()).expect("printed name-addr has no NUL bytes")
+    }
+
+    fn as_uri_ref(&self) -> PjSipUriRef {
+        PjSipUriRef::from(self.pjsip_name_addr as *const pj::pjsip_uri)
+    }
+}
+
+impl std::str::FromStr for PjSipNameAddr {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse(CString::new(s)?)
+    }
+}
+
+impl Clone for PjSipNameAddr {
+    fn clone(&self) -> Self {
+        let mut pool = uri_pool();
+        let pjsip_name_addr = clone_uri(&mut pool, &self.as_uri_ref()) as *mut _;
+
+        Self {
+            pjsip_name_addr,
+            pool,
+        }
+    }
+}
+
+impl PartialEq for PjSipNameAddr {
+    fn eq(&self, other: &Self) -> bool {
+        uri_eq(&self.as_uri_ref(), &other.as_uri_ref())
+    }
+}
+
+impl Display for PjSipNameAddr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            self.as_uri_ref()
+                .print(pj::pjsip_uri_context_e_PJSIP_URI_IN_FROMTO_HDR)
+        )
+    }
+}
+
+impl Debug for PjSipNameAddr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "PjSipNameAddr({self})")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escape_keeps_allowed() {
+        assert_eq!(
+            escape("alice.smith+1;x=y", USER_UNRESERVED),
+            "alice.smith+1;x=y"
+        );
+        assert_eq!(escape("sip:a@b", HNV_UNRESERVED), "sip:a%40b");
+    }
+
+    #[test]
+    fn escape_reserved() {
+        assert_eq!(escape("a b@c", USER_UNRESERVED), "a%20b%40c");
+        assert_eq!(escape("x;y=z", PARAM_UNRESERVED), "x%3By%3Dz");
+        assert_eq!(escape("a&b=c", HNV_UNRESERVED), "a%26b%3Dc");
+        assert_eq!(escape("100%", PARAM_UNRESERVED), "100%25");
+        assert_eq!(escape("é", PARAM_UNRESERVED), "%C3%A9");
+    }
+
+    #[test]
+    fn check_host_rejects_delimiters() {
+        for host in ["example.com", "10.0.0.1", "[2001:db8::1]", "2001:db8::1"] {
+            assert!(check_host(host).is_ok(), "{host}");
+        }
+        for host in ["", "a b", "a;b", "a@b", "a?b", "a>b"] {
+            assert!(check_host(host).is_err(), "{host}");
+        }
+    }
+
+    #[test]
+    fn quote_round_trips() {
+        for display in ["Alice", r#"Al "Ice" \ Cream"#, r"\", "\""] {
+            let quoted = quote(display);
+            assert!(!quoted
+                .replace(r"\\", "")
+                .replace(r#"\""#, "")
+                .contains(['"', '\\']));
+            assert_eq!(unquote(&quoted), display);
+        }
+    }
+}
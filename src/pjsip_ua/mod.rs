@@ -1,3 +1,5 @@
 pub mod sip_inv;
+pub mod sip_regc;
 
 pub use sip_inv::*;
+pub use sip_regc::*;
@@ -1,30 +1,33 @@
 use std::{
+    any::Any,
     ffi::{CStr, CString},
     fmt::{Debug, Display},
-    marker::PhantomData,
-    os::raw::c_void,
     sync::Arc,
 };
 
 use itertools::Itertools;
+use parking_lot::RwLock;
 use pjproject_sys as pj;
 
 use crate::{
-    Error, PjMediaEndpt, PjMediaSdpSession, PjMediaSdpSessionRef, PjMediaStreamInfo, PjSipDialog,
-    PjSipEvent, PjSipTxData, PjStatus,
+    get_mod_data, pj_str_to_string, remove_mod_data, set_mod_data, Error, PjMediaEndpt,
+    PjMediaSdpSession, PjMediaSdpSessionRef, PjMediaStreamInfo, PjSipDialog, PjSipEndpoint,
+    PjSipEvent, PjSipModule, PjSipRole, PjSipRxData, PjSipTransaction, PjSipTxData, PjSipUriRef,
+    PjSipUserAgentRef, PjStatus, PjTimeVal, PJ_ENOTSUP,
 };
 
-#[derive(Clone)]
-pub struct PjSipInvSession<T> {
+/** Handle to an invite session. Each handle holds a reference on the
+ * session and a session count on its dialog, so both stay alive while
+ * Rust holds it, and cloning is cheap. */
+pub struct PjSipInvSession {
     pjsip_inv_session: *mut pj::pjsip_inv_session,
-    phantom: PhantomData<T>,
+    pjsip_dialog: *mut pj::pjsip_dialog,
 }
 
-unsafe impl<T> Send for PjSipInvSession<T> {}
-unsafe impl<T> Sync for PjSipInvSession<T> {}
+unsafe impl Send for PjSipInvSession {}
+unsafe impl Sync for PjSipInvSession {}
 
-impl<T> PjSipInvSession<T> {
-    // NB: After this function is called, dialog may be destroyed.
+impl PjSipInvSession {
     pub fn create_uac(
         dialog: &mut PjSipDialog,
         local_sdp: &PjMediaSdpSession,
@@ -40,10 +43,79 @@ impl<T> PjSipInvSession<T> {
             )
         };
 
-        PjStatus::result_for_status(status).map(|_| Self {
-            pjsip_inv_session: inv_sess,
-            phantom: PhantomData,
-        })
+        PjStatus::result_for_status(status)?;
+
+        let mut inv = Self::from(inv_sess);
+        inv.record_state();
+
+        Ok(inv)
+    }
+
+    /** Check that an incoming INVITE can be handled with the given options
+     * and local SDP. On failure pjsip has already answered the request
+     * statelessly with the appropriate final response. Returns the options
+     * the session should be created with. */
+    pub fn verify_request(
+        rdata: &mut PjSipRxData,
+        options: u32,
+        local_sdp: Option<&PjMediaSdpSession>,
+        sip_endpt: &PjSipEndpoint,
+    ) -> Result<u32, Error> {
+        let mut options = options;
+        let status = unsafe {
+            pj::pjsip_inv_verify_request(
+                rdata.as_mut_ptr(),
+                &mut options,
+                local_sdp.map(|s| s.as_ptr()).unwrap_or(std::ptr::null()),
+                std::ptr::null_mut(),
+                sip_endpt.as_mut_ptr(),
+                std::ptr::null_mut(),
+            )
+        };
+
+        PjStatus::result_for_status(status).map(|_| options)
+    }
+
+    /** Create the UAS dialog and invite session for an incoming INVITE. The
+     * request should have been checked with `verify_request` first. */
+    pub fn create_uas<S: AsRef<CStr>>(
+        ua: PjSipUserAgentRef,
+        rdata: &mut PjSipRxData,
+        local_contact: S,
+        local_sdp: Option<&PjMediaSdpSession>,
+        options: u32,
+    ) -> Result<Self, Error> {
+        let mut dialog = std::ptr::null_mut();
+        let status = unsafe {
+            pj::pjsip_dlg_create_uas_and_inc_lock(
+                ua.as_ptr() as *mut _,
+                rdata.as_mut_ptr(),
+                &pj::pj_str(local_contact.as_ref().as_ptr() as *mut _),
+                &mut dialog,
+            )
+        };
+        PjStatus::result_for_status(status)?;
+
+        let mut inv_sess = std::ptr::null_mut();
+        let status = unsafe {
+            pj::pjsip_inv_create_uas(
+                dialog,
+                rdata.as_mut_ptr(),
+                local_sdp.map(|s| s.as_ptr()).unwrap_or(std::ptr::null()),
+                options,
+                &mut inv_sess,
+            )
+        };
+
+        /* Take our reference before releasing the dialog, which is destroyed
+         * here if the session could not be created. */
+        let inv = PjStatus::result_for_status(status).map(|_| Self::from(inv_sess));
+        unsafe { pj::pjsip_dlg_dec_lock(dialog) };
+
+        let mut inv = inv?;
+        inv.record_state();
+
+        Ok(inv)
     }
 
     pub fn as_ptr(&self) -> *const pj::pjsip_inv_session {
@@ -70,33 +142,43 @@ impl<T> PjSipInvSession<T> {
         }
     }
 
-    pub fn insert_mod_data(&mut self, idx: usize, mod_data: T) {
+    /** Attach data for `module` to the session, replacing any data it
+     * attached before. The data is released when pjsip destroys the
+     * session's dialog, which no handle can outlive. */
+    pub fn set_mod_data<D: Any + Send + Sync>(
+        &mut self,
+        module: &PjSipModule,
+        data: D,
+    ) -> Result<(), Error> {
         unsafe {
-            let mod_data = Arc::new(mod_data);
-            let idx = idx % (*self.pjsip_inv_session).mod_data.len();
-            if !(*self.pjsip_inv_session).mod_data[idx].is_null() {
-                let old_mod_data = Arc::from_raw((*self.pjsip_inv_session).mod_data[idx]);
-                drop(old_mod_data);
-            }
-
-            (*self.pjsip_inv_session).mod_data[idx] = Arc::into_raw(mod_data) as *mut c_void;
+            set_mod_data(
+                std::ptr::addr_of_mut!((*self.pjsip_inv_session).mod_data),
+                (*self.pjsip_dialog).grp_lock_,
+                (*self.pjsip_dialog).pool,
+                module,
+                data,
+            )
         }
     }
 
-    pub fn get_mod_data(&self, idx: usize) -> Option<Arc<T>> {
+    /** Data attached by `module`, if it is of type `D`. */
+    pub fn mod_data<D: Any + Send + Sync>(&self, module: &PjSipModule) -> Option<Arc<D>> {
         unsafe {
-            if idx >= (*self.pjsip_inv_session).mod_data.len() {
-                return None;
-            }
+            get_mod_data(
+                std::ptr::addr_of!((*self.pjsip_inv_session).mod_data),
+                (*self.pjsip_dialog).grp_lock_,
+                module,
+            )
+        }
+    }
 
-            let data = (*self.pjsip_inv_session).mod_data[idx] as *mut T;
-            if data.is_null() {
-                return None;
-            }
-            let data = Arc::from_raw(data);
-            let ret = data.clone();
-            std::mem::forget(data);
-            Some(ret)
+    pub fn remove_mod_data(&mut self, module: &PjSipModule) {
+        unsafe {
+            remove_mod_data(
+                std::ptr::addr_of_mut!((*self.pjsip_inv_session).mod_data),
+                (*self.pjsip_dialog).grp_lock_,
+                module,
+            )
         }
     }
 
@@ -104,6 +186,61 @@ impl<T> PjSipInvSession<T> {
         PjSipTxData::inv_invite(&self)
     }
 
+    /** Create the first response to the INVITE that created this session,
+     * e.g. 180 to start ringing or a 4xx-6xx to reject the call. */
+    pub fn initial_answer<S: AsRef<CStr>>(
+        &mut self,
+        rdata: &mut PjSipRxData,
+        status_code: u16,
+        status_text: Option<S>,
+        local_sdp: Option<&PjMediaSdpSession>,
+    ) -> Result<PjSipTxData, Error> {
+        PjSipTxData::inv_initial_answer(self, rdata, status_code, status_text, local_sdp)
+    }
+
+    /** Create a subsequent response to the INVITE, e.g. 200 with the answer
+     * SDP once the call is picked up. */
+    pub fn answer<S: AsRef<CStr>>(
+        &mut self,
+        status_code: u16,
+        status_text: Option<S>,
+        local_sdp: Option<&PjMediaSdpSession>,
+    ) -> Result<PjSipTxData, Error> {
+        PjSipTxData::inv_answer(self, status_code, status_text, local_sdp)
+    }
+
+    /** Set the answer to an offer received from the peer. Usually done by
+     * returning the answer from `on_rx_offer` instead. */
+    pub fn set_sdp_answer(&mut self, sdp: &PjMediaSdpSession) -> Result<(), Error> {
+        let status = unsafe { pj::pjsip_inv_set_sdp_answer(self.pjsip_inv_session, sdp.as_ptr()) };
+
+        PjStatus::result_for_status(status)
+    }
+
+    /** Create the ACK for the 2xx response with the given CSeq, for use
+     * from `on_send_ack`. */
+    pub fn create_ack(&mut self, cseq: i32) -> Result<PjSipTxData, Error> {
+        PjSipTxData::inv_ack(self, cseq)
+    }
+
+    /** Carry out a redirect decision that was left `Pending` in
+     * `on_redirected`. */
+    pub fn process_redirect(
+        &mut self,
+        op: PjSipRedirectOp,
+        evt: Option<&mut PjSipEvent>,
+    ) -> Result<(), Error> {
+        let status = unsafe {
+            pj::pjsip_inv_process_redirect(
+                self.pjsip_inv_session,
+                op.into(),
+                evt.map(|e| e.as_mut_ptr()).unwrap_or(std::ptr::null_mut()),
+            )
+        };
+
+        PjStatus::result_for_status(status)
+    }
+
     pub fn send_msg(&mut self, tx_data: &mut PjSipTxData) -> Result<(), Error> {
         let status =
             unsafe { pj::pjsip_inv_send_msg(self.pjsip_inv_session, tx_data.as_mut_ptr()) };
@@ -112,17 +249,250 @@ impl<T> PjSipInvSession<T> {
     }
 
     pub fn end_session(&mut self) -> Result<(), Error> {
-        let mut tx_data = PjSipTxData::inv_end_session(&self, None::<&CStr>)?;
+        self.end_session_with(603, None::<&CStr>, &[])
+    }
 
-        self.send_msg(&mut tx_data)?;
+    /** End the session with the given status code and extra headers, e.g.
+     * a `Reason` header built with `PjSipReason`. pjsip picks the request
+     * from the state: CANCEL in `Calling`/`Early` as UAC, a final response
+     * with `status_code` in `Incoming`/`Early` as UAS, BYE once
+     * `Connecting`/`Confirmed`. The status code only matters for the UAS
+     * response. A CANCEL before any provisional response is deferred by
+     * pjsip and sent without the extra headers. */
+    pub fn end_session_with<S: AsRef<CStr>>(
+        &mut self,
+        status_code: u16,
+        reason_text: Option<S>,
+        extra_headers: &[(&CStr, &CStr)],
+    ) -> Result<(), Error> {
+        let tx_data = PjSipTxData::inv_end_session(self, status_code, reason_text)?;
+
+        if let Some(mut tx_data) = tx_data {
+            for (name, value) in extra_headers {
+                tx_data.add_header(name, value)?;
+            }
+            self.send_msg(&mut tx_data)?;
+        }
 
         Ok(())
     }
 
+    /** Send a re-INVITE to renegotiate media, offering `new_sdp` or, if
+     * `None`, the current local SDP. The outcome is reported through
+     * `on_media_update`. */
+    pub fn reinvite(&mut self, new_sdp: Option<&PjMediaSdpSession>) -> Result<(), Error> {
+        let mut tx_data = PjSipTxData::inv_reinvite(self, new_sdp.map(|s| &**s))?;
+
+        self.send_msg(&mut tx_data)
+    }
+
+    /** Same as `reinvite` but with an UPDATE, which the peer must support
+     * (RFC 3311). Unlike a re-INVITE it can also be sent before the call
+     * is answered. */
+    pub fn update(&mut self, new_sdp: Option<&PjMediaSdpSession>) -> Result<(), Error> {
+        let mut tx_data = PjSipTxData::inv_update(self, new_sdp.map(|s| &**s))?;
+
+        self.send_msg(&mut tx_data)
+    }
+
+    /** Put the call on hold with a re-INVITE offering the active local SDP
+     * as `sendonly`, or `inactive` if we were not receiving anyway. The
+     * directions before the hold are kept for `unhold`. */
+    pub fn hold(&mut self) -> Result<(), Error> {
+        let local_sdp = self.get_active_local_neg_sdp()?;
+        let pre_hold = self
+            .info()
+            .and_then(|info| info.pre_hold_directions)
+            .unwrap_or_else(|| {
+                let mut directions = [0u8; MAX_SDP_MEDIA];
+                let current = unsafe { sdp_directions(local_sdp.as_ptr()) };
+                for (direction, current) in directions.iter_mut().zip(current) {
+                    *direction = SDP_DIRECTIONS
+                        .iter()
+                        .position(|dir| *dir == current)
+                        .unwrap_or(0) as u8;
+                }
+
+                directions
+            });
+
+        self.reinvite_with_direction(|_, current| {
+            if current == c"recvonly" || current == c"inactive" {
+                c"inactive"
+            } else {
+                c"sendonly"
+            }
+        })?;
+        self.set_pre_hold_directions(Some(pre_hold));
+
+        Ok(())
+    }
+
+    /** Take the call off hold with a re-INVITE offering the active local
+     * SDP with the directions it had before `hold`, `sendrecv` if the call
+     * was not put on hold by us. */
+    pub fn unhold(&mut self) -> Result<(), Error> {
+        let pre_hold = self.info().and_then(|info| info.pre_hold_directions);
+
+        self.reinvite_with_direction(|i, _| match pre_hold {
+            Some(directions) => directions
+                .get(i)
+                .and_then(|dir| SDP_DIRECTIONS.get(*dir as usize))
+                .copied()
+                .unwrap_or(c"sendrecv"),
+            None => c"sendrecv",
+        })?;
+        self.set_pre_hold_directions(None);
+
+        Ok(())
+    }
+
+    fn reinvite_with_direction(
+        &mut self,
+        direction: impl Fn(usize, &CStr) -> &'static CStr,
+    ) -> Result<(), Error> {
+        let local_sdp = self.get_active_local_neg_sdp()?;
+
+        /* The offer is copied by pjsip, so it only needs to live until the
+         * re-INVITE has been created. */
+        let endpt = unsafe { (*self.as_ref().dlg).endpt };
+        let pool = unsafe { pj::pjsip_endpt_create_pool(endpt, c"inv-hold".as_ptr(), 1000, 1000) };
+        if pool.is_null() {
+            return Err(Error::Validation("Failed to create pool".into()));
+        }
+
+        let result = unsafe {
+            let sdp = pj::pjmedia_sdp_session_clone(pool, local_sdp.as_ptr());
+            if sdp.is_null() {
+                pj::pjsip_endpt_release_pool(endpt, pool);
+                return Err(Error::Validation("Failed to clone local SDP".into()));
+            }
+
+            set_sdp_direction(pool, sdp, direction);
+            PjSipTxData::inv_reinvite(self, Some(&PjMediaSdpSessionRef::from(sdp)))
+        };
+
+        unsafe { pj::pjsip_endpt_release_pool(endpt, pool) };
+
+        self.send_msg(&mut result?)
+    }
+
     pub fn get_state(&self) -> PjSipInvState {
         unsafe { ((*self.pjsip_inv_session).state as u8).into() }
     }
 
+    pub fn role(&self) -> PjSipRole {
+        (self.as_ref().role as u8).into()
+    }
+
+    /** Status code explaining why the session ended, e.g. 486 when the
+     * peer was busy, 487 when the INVITE was cancelled, 408 on timeout or
+     * 200 after a normal BYE. */
+    pub fn cause(&self) -> u16 {
+        self.as_ref().cause as _
+    }
+
+    pub fn cause_text(&self) -> String {
+        pj_str_to_string(&self.as_ref().cause_text)
+    }
+
+    /** Last status code seen on the INVITE transaction. */
+    pub fn last_status_code(&self) -> Option<u16> {
+        self.info()
+            .map(|info| info.last_status_code)
+            .filter(|code| *code != 0)
+    }
+
+    pub fn last_status_text(&self) -> Option<String> {
+        self.info()
+            .filter(|info| info.last_status_code != 0)
+            .map(|info| pj_str_to_string(&info.last_status_text))
+    }
+
+    pub fn created_at(&self) -> Option<PjTimeVal> {
+        self.info().and_then(|info| info.created)
+    }
+
+    /** When the session was first confirmed, i.e. the call was answered. */
+    pub fn connected_at(&self) -> Option<PjTimeVal> {
+        self.info().and_then(|info| info.connected)
+    }
+
+    pub fn disconnected_at(&self) -> Option<PjTimeVal> {
+        self.info().and_then(|info| info.disconnected)
+    }
+
+    fn info(&self) -> Option<PjSipInvInfo> {
+        let slot = info_slot()?;
+        let info = self.as_ref().mod_data[slot] as *const PjSipInvInfo;
+        if info.is_null() {
+            return None;
+        }
+
+        Some(unsafe { info.read_unaligned() })
+    }
+
+    /* Update the bookkeeping for the current state, called on creation and
+     * on every state change. */
+    fn record_state(&mut self) {
+        let Some(slot) = info_slot() else {
+            return;
+        };
+
+        let now = PjTimeVal::timeofday().ok();
+        let state = self.get_state();
+        let mut info = self.info().unwrap_or(PjSipInvInfo {
+            created: now,
+            connected: None,
+            disconnected: None,
+            last_status_code: 0,
+            last_status_text: unsafe { std::mem::zeroed() },
+            pre_hold_directions: None,
+        });
+
+        unsafe {
+            let inv = &mut *self.pjsip_inv_session;
+
+            match state {
+                PjSipInvState::Confirmed if info.connected.is_none() => info.connected = now,
+                PjSipInvState::Disconnected if info.disconnected.is_none() => {
+                    info.disconnected = now
+                }
+                _ => {}
+            }
+
+            if !inv.invite_tsx.is_null() {
+                let tsx = &*inv.invite_tsx;
+                if tsx.status_code > 0 && tsx.status_code as u16 != info.last_status_code {
+                    info.last_status_code = tsx.status_code as _;
+                    pj::pj_strdup(inv.pool, &mut info.last_status_text, &tsx.status_text);
+                }
+            }
+
+            if inv.mod_data[slot].is_null() {
+                inv.mod_data[slot] =
+                    pj::pj_pool_alloc(inv.pool, std::mem::size_of::<PjSipInvInfo>() as _);
+                if inv.mod_data[slot].is_null() {
+                    return;
+                }
+            }
+            /* Pool memory is not guaranteed to be aligned for the struct */
+            (inv.mod_data[slot] as *mut PjSipInvInfo).write_unaligned(info);
+        }
+    }
+
+    fn set_pre_hold_directions(&mut self, directions: Option<[u8; MAX_SDP_MEDIA]>) {
+        let Some(slot) = info_slot() else {
+            return;
+        };
+        let Some(mut info) = self.info() else {
+            return;
+        };
+
+        info.pre_hold_directions = directions;
+        unsafe { (self.as_ref().mod_data[slot] as *mut PjSipInvInfo).write_unaligned(info) };
+    }
+
     pub fn get_active_local_neg_sdp(&self) -> Result<PjMediaSdpSessionRef, Error> {
         let mut sdp = unsafe { std::mem::zeroed() };
         let status = unsafe {
@@ -152,25 +522,132 @@ impl<T> PjSipInvSession<T> {
     }
 }
 
-impl<T> Drop for PjSipInvSession<T> {
+/* Call bookkeeping pjsip does not keep itself. It lives in the session's
+ * pool, in the mod_data slot of the invite usage module which pjsip leaves
+ * unused on the session. */
+#[derive(Clone, Copy)]
+struct PjSipInvInfo {
+    created: Option<PjTimeVal>,
+    connected: Option<PjTimeVal>,
+    disconnected: Option<PjTimeVal>,
+    last_status_code: u16,
+    last_status_text: pj::pj_str_t,
+    /* Index in SDP_DIRECTIONS of every media line's direction before the
+     * call was put on hold, None when not on hold */
+    pre_hold_directions: Option<[u8; MAX_SDP_MEDIA]>,
+}
+
+fn info_slot() -> Option<usize> {
+    let id = unsafe { (*pj::pjsip_inv_usage_instance()).id };
+
+    usize::try_from(id).ok()
+}
+
+const SDP_DIRECTIONS: [&CStr; 4] = [c"sendrecv", c"sendonly", c"recvonly", c"inactive"];
+
+const MAX_SDP_MEDIA: usize = 16;
+
+/* Direction of every media line, taken from the media line or else the
+ * session level */
+unsafe fn sdp_directions(sdp: *const pj::pjmedia_sdp_session) -> Vec<&'static CStr> {
+    let session_dir = SDP_DIRECTIONS
+        .into_iter()
+        .find(|dir| {
+            !pj::pjmedia_sdp_attr_find2(
+                (*sdp).attr_count,
+                (*sdp).attr.as_ptr(),
+                dir.as_ptr(),
+                std::ptr::null(),
+            )
+            .is_null()
+        })
+        .unwrap_or(c"sendrecv");
+
+    (*sdp)
+        .media
+        .iter()
+        .take((*sdp).media_count as _)
+        .map(|m| {
+            SDP_DIRECTIONS
+                .into_iter()
+                .find(|dir| {
+                    !pj::pjmedia_sdp_media_find_attr2(*m, dir.as_ptr(), std::ptr::null()).is_null()
+                })
+                .unwrap_or(session_dir)
+        })
+        .collect()
+}
+
+/* Replace the direction attribute of every active media line, given its
+ * index and current direction. */
+unsafe fn set_sdp_direction(
+    pool: *mut pj::pj_pool_t,
+    sdp: *mut pj::pjmedia_sdp_session,
+    direction: impl Fn(usize, &CStr) -> &'static CStr,
+) {
+    let current = sdp_directions(sdp);
+
+    for dir in SDP_DIRECTIONS {
+        pj::pjmedia_sdp_attr_remove_all(
+            &mut (*sdp).attr_count,
+            (*sdp).attr.as_mut_ptr(),
+            dir.as_ptr(),
+        );
+    }
+
+    for (i, m) in (*sdp)
+        .media
+        .iter()
+        .take((*sdp).media_count as _)
+        .enumerate()
+    {
+        let m = *m;
+        if (*m).desc.port == 0 {
+            continue;
+        }
+
+        for dir in SDP_DIRECTIONS {
+            pj::pjmedia_sdp_media_remove_all_attr(m, dir.as_ptr());
+        }
+
+        let attr =
+            pj::pjmedia_sdp_attr_create(pool, direction(i, current[i]).as_ptr(), std::ptr::null());
+        if !attr.is_null() {
+            pj::pjmedia_sdp_media_add_attr(m, attr);
+        }
+    }
+}
+
+impl Clone for PjSipInvSession {
+    fn clone(&self) -> Self {
+        Self::from(self.pjsip_inv_session)
+    }
+}
+
+impl Drop for PjSipInvSession {
     fn drop(&mut self) {
         unsafe {
-            for ptr in (*self.pjsip_inv_session).mod_data.iter_mut() {
-                if !ptr.is_null() {
-                    let mod_data = Arc::from_raw(*ptr as *mut T);
-                    drop(mod_data);
-                }
-                *ptr = std::ptr::null_mut();
-            }
+            /* Dialog first, so the data tied to it is released while the
+             * session is still alive. */
+            pj::pjsip_dlg_dec_session(self.pjsip_dialog, pj::pjsip_inv_usage_instance());
+            pj::pjsip_inv_dec_ref(self.pjsip_inv_session);
         }
     }
 }
 
-impl<T> From<*mut pj::pjsip_inv_session> for PjSipInvSession<T> {
+/** Take a new handle on a session owned by pjsip. */
+impl From<*mut pj::pjsip_inv_session> for PjSipInvSession {
+    #[allow(clippy::not_unsafe_ptr_arg_deref)]
     fn from(value: *mut pj::pjsip_inv_session) -> Self {
-        Self {
-            pjsip_inv_session: value,
-            phantom: PhantomData,
+        unsafe {
+            let dialog = (*value).dlg;
+            pj::pjsip_inv_add_ref(value);
+            pj::pjsip_dlg_inc_session(dialog, pj::pjsip_inv_usage_instance());
+
+            Self {
+                pjsip_inv_session: value,
+                pjsip_dialog: dialog,
+            }
         }
     }
 }
@@ -228,99 +705,427 @@ impl Debug for PjSipInvState {
     }
 }
 
-pub struct PjSipInvCallback<T> {
-    pjsip_inv_callback: pj::pjsip_inv_callback,
-    pub on_state_changed: Option<fn(PjSipInvSession<T>, PjSipEvent)>,
-    pub on_media_update: Option<fn(PjSipInvSession<T>, PjStatus)>,
+/** What the invite session should do with a re-INVITE reported to
+ * `on_rx_reinvite`. */
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum PjSipInvReinviteAction {
+    /** The application answers the re-INVITE itself with `answer`. */
+    Manual,
+    /** Let pjsip handle the re-INVITE, calling `on_rx_offer` if it carries
+     * an offer. */
+    Default,
 }
 
-impl<T> Default for PjSipInvCallback<T> {
-    fn default() -> Self {
-        Self {
-            pjsip_inv_callback: pj::pjsip_inv_callback {
-                on_state_changed: None,
-                on_new_session: None,
-                on_tsx_state_changed: None,
-                on_rx_offer: None,
-                on_rx_offer2: None,
-                on_rx_reinvite: None,
-                on_create_offer: None,
-                on_media_update: None,
-                on_send_ack: None,
-                on_redirected: None,
-            },
-            on_state_changed: None,
-            on_media_update: None,
-        }
-    }
+/** What to do with a redirect target reported to `on_redirected`. */
+#[repr(u8)]
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum PjSipRedirectOp {
+    /** Reject the target and try the next one, if any. */
+    Reject = 0,
+    /** Accept the target and send the INVITE to it. */
+    Accept = 1,
+    /** Accept the target and replace the To header with it. */
+    AcceptReplace = 2,
+    /** Decide later with `process_redirect`. */
+    Pending = 3,
+    /** Stop redirection and disconnect the session. */
+    Stop = 4,
 }
 
-impl<T> PjSipInvCallback<T> {
-    pub fn as_ptr(&self) -> *const pj::pjsip_inv_callback {
-        &self.pjsip_inv_callback
+impl From<PjSipRedirectOp> for pj::pjsip_redirect_op {
+    fn from(value: PjSipRedirectOp) -> Self {
+        value as _
     }
+}
+
+type OnInvEvt = Box<dyn Fn(&mut PjSipInvSession, &mut PjSipEvent) + Send + Sync>;
+type OnInvStatus = Box<dyn Fn(&PjSipInvSession, PjStatus) + Send + Sync>;
+type OnInvTsxEvt =
+    Box<dyn Fn(&mut PjSipInvSession, &mut PjSipTransaction, &mut PjSipEvent) + Send + Sync>;
+type OnInvRxOffer = Box<
+    dyn Fn(&mut PjSipInvSession, &PjMediaSdpSessionRef) -> Option<PjMediaSdpSession> + Send + Sync,
+>;
+type OnInvRxOffer2 = Box<
+    dyn Fn(&mut PjSipInvSession, &PjMediaSdpSessionRef, &PjSipRxData) -> Option<PjMediaSdpSession>
+        + Send
+        + Sync,
+>;
+type OnInvRxReinvite = Box<
+    dyn Fn(
+            &mut PjSipInvSession,
+            Option<&PjMediaSdpSessionRef>,
+            &mut PjSipRxData,
+        ) -> PjSipInvReinviteAction
+        + Send
+        + Sync,
+>;
+type OnInvCreateOffer =
+    Box<dyn Fn(&mut PjSipInvSession) -> Option<PjMediaSdpSession> + Send + Sync>;
+type OnInvSendAck = Box<dyn Fn(&mut PjSipInvSession, &mut PjSipRxData) + Send + Sync>;
+type OnInvRedirected =
+    Box<dyn Fn(&mut PjSipInvSession, &PjSipUriRef, &PjSipEvent) -> PjSipRedirectOp + Send + Sync>;
+
+/* pjsip keeps a single copy of the invite callbacks and hands them nothing
+ * but the session, so the Rust closures are kept here keyed by the endpoint
+ * the invite usage was initialized on. The trampolines find them through
+ * the session's dialog. */
+static INV_CALLBACKS: RwLock<Vec<(usize, Arc<PjSipInvCallback>)>> =
+    parking_lot::const_rwlock(Vec::new());
 
+#[derive(Default)]
+pub struct PjSipInvCallback {
+    on_state_changed: Option<OnInvEvt>,
+    on_new_session: Option<OnInvEvt>,
+    on_tsx_state_changed: Option<OnInvTsxEvt>,
+    on_rx_offer: Option<OnInvRxOffer>,
+    on_rx_offer2: Option<OnInvRxOffer2>,
+    on_rx_reinvite: Option<OnInvRxReinvite>,
+    on_create_offer: Option<OnInvCreateOffer>,
+    on_media_update: Option<OnInvStatus>,
+    on_send_ack: Option<OnInvSendAck>,
+    on_redirected: Option<OnInvRedirected>,
+}
+
+impl PjSipInvCallback {
     pub fn with_on_state_changed<F>(&mut self, cb: F) -> &mut Self
     where
-        F: Fn(&mut PjSipInvSession<T>, &mut PjSipEvent),
+        F: Fn(&mut PjSipInvSession, &mut PjSipEvent) + Send + Sync + 'static,
     {
-        self.pjsip_inv_callback.on_state_changed = Some(Self::wrap_inv_evt(cb));
+        self.on_state_changed = Some(Box::new(cb));
 
         self
     }
 
     pub fn with_on_new_session<F>(&mut self, cb: F) -> &mut Self
     where
-        F: Fn(&mut PjSipInvSession<T>, &mut PjSipEvent),
+        F: Fn(&mut PjSipInvSession, &mut PjSipEvent) + Send + Sync + 'static,
     {
-        self.pjsip_inv_callback.on_new_session = Some(Self::wrap_inv_evt(cb));
+        self.on_new_session = Some(Box::new(cb));
+
+        self
+    }
+
+    /** Called for every state change of a transaction within the session,
+     * including the ones for requests other than INVITE. */
+    pub fn with_on_tsx_state_changed<F>(&mut self, cb: F) -> &mut Self
+    where
+        F: Fn(&mut PjSipInvSession, &mut PjSipTransaction, &mut PjSipEvent) + Send + Sync + 'static,
+    {
+        self.on_tsx_state_changed = Some(Box::new(cb));
+
+        self
+    }
+
+    /** Called when the peer sends a new offer. Return the answer SDP, or
+     * `None` to let pjsip answer with the current local SDP. */
+    pub fn with_on_rx_offer<F>(&mut self, cb: F) -> &mut Self
+    where
+        F: Fn(&mut PjSipInvSession, &PjMediaSdpSessionRef) -> Option<PjMediaSdpSession>
+            + Send
+            + Sync
+            + 'static,
+    {
+        self.on_rx_offer = Some(Box::new(cb));
+
+        self
+    }
+
+    /** Same as `with_on_rx_offer` but also hands over the message carrying
+     * the offer. pjsip calls this one instead of `on_rx_offer` when both
+     * are set. */
+    pub fn with_on_rx_offer2<F>(&mut self, cb: F) -> &mut Self
+    where
+        F: Fn(
+                &mut PjSipInvSession,
+                &PjMediaSdpSessionRef,
+                &PjSipRxData,
+            ) -> Option<PjMediaSdpSession>
+            + Send
+            + Sync
+            + 'static,
+    {
+        self.on_rx_offer2 = Some(Box::new(cb));
+
+        self
+    }
+
+    /** Called when a re-INVITE is received, with its offer if it has one.
+     * Returning `Manual` leaves the re-INVITE to be answered later with
+     * `answer`. */
+    pub fn with_on_rx_reinvite<F>(&mut self, cb: F) -> &mut Self
+    where
+        F: Fn(
+                &mut PjSipInvSession,
+                Option<&PjMediaSdpSessionRef>,
+                &mut PjSipRxData,
+            ) -> PjSipInvReinviteAction
+            + Send
+            + Sync
+            + 'static,
+    {
+        self.on_rx_reinvite = Some(Box::new(cb));
+
+        self
+    }
+
+    /** Called when pjsip needs a new local offer, e.g. for a re-INVITE or
+     * UPDATE without SDP. Return `None` to reuse the current local SDP. */
+    pub fn with_on_create_offer<F>(&mut self, cb: F) -> &mut Self
+    where
+        F: Fn(&mut PjSipInvSession) -> Option<PjMediaSdpSession> + Send + Sync + 'static,
+    {
+        self.on_create_offer = Some(Box::new(cb));
 
         self
     }
 
     pub fn with_on_media_update<F>(&mut self, cb: F) -> &mut Self
     where
-        F: Fn(&PjSipInvSession<T>, PjStatus),
+        F: Fn(&PjSipInvSession, PjStatus) + Send + Sync + 'static,
     {
-        self.pjsip_inv_callback.on_media_update = Some(Self::wrap_inv_status(cb));
+        self.on_media_update = Some(Box::new(cb));
 
         self
     }
 
-    fn wrap_inv_evt<F: Fn(&mut PjSipInvSession<T>, &mut PjSipEvent)>(
-        _: F,
-    ) -> unsafe extern "C" fn(inv: *mut pj::pjsip_inv_session, evt: *mut pj::pjsip_event) {
-        assert!(std::mem::size_of::<F>() == 0);
-
-        unsafe extern "C" fn wrapped<T, F: Fn(&mut PjSipInvSession<T>, &mut PjSipEvent)>(
-            inv_ptr: *mut pj::pjsip_inv_session,
-            evt_ptr: *mut pj::pjsip_event,
-        ) {
-            let mut inv = PjSipInvSession::from(inv_ptr);
-            let mut evt = PjSipEvent::from(evt_ptr);
-            std::mem::transmute::<_, &F>(&())(&mut inv, &mut evt);
-            std::mem::forget(inv);
-            std::mem::forget(evt);
-        }
+    /** Called when an ACK needs to be sent for a 2xx response. The handler
+     * takes over sending it, see `create_ack`. */
+    pub fn with_on_send_ack<F>(&mut self, cb: F) -> &mut Self
+    where
+        F: Fn(&mut PjSipInvSession, &mut PjSipRxData) + Send + Sync + 'static,
+    {
+        self.on_send_ack = Some(Box::new(cb));
+
+        self
+    }
+
+    /** Called for each target of a 3xx response. Without this handler
+     * pjsip does not follow redirects. */
+    pub fn with_on_redirected<F>(&mut self, cb: F) -> &mut Self
+    where
+        F: Fn(&mut PjSipInvSession, &PjSipUriRef, &PjSipEvent) -> PjSipRedirectOp
+            + Send
+            + Sync
+            + 'static,
+    {
+        self.on_redirected = Some(Box::new(cb));
+
+        self
+    }
+
+    /** Store the callbacks for `sip_endpt` and return the pjsip callback
+     * table pointing at the trampolines for them. */
+    pub(crate) fn register(self, sip_endpt: &PjSipEndpoint) -> pj::pjsip_inv_callback {
+        /* Only hooks with a handler are installed, pjsip changes behaviour
+         * depending on which ones are present. */
+        let pjsip_inv_callback = pj::pjsip_inv_callback {
+            /* pjsip requires these two to be set */
+            on_state_changed: Some(on_state_changed),
+            on_new_session: Some(on_new_session),
+            on_tsx_state_changed: self
+                .on_tsx_state_changed
+                .as_ref()
+                .map(|_| on_tsx_state_changed as _),
+            on_rx_offer: self.on_rx_offer.as_ref().map(|_| on_rx_offer as _),
+            on_rx_offer2: self.on_rx_offer2.as_ref().map(|_| on_rx_offer2 as _),
+            on_rx_reinvite: self.on_rx_reinvite.as_ref().map(|_| on_rx_reinvite as _),
+            on_create_offer: self.on_create_offer.as_ref().map(|_| on_create_offer as _),
+            on_media_update: self.on_media_update.as_ref().map(|_| on_media_update as _),
+            on_send_ack: self.on_send_ack.as_ref().map(|_| on_send_ack as _),
+            on_redirected: self.on_redirected.as_ref().map(|_| on_redirected as _),
+        };
+
+        let endpt = sip_endpt.as_mut_ptr() as usize;
+        let mut callbacks = INV_CALLBACKS.write();
+        callbacks.retain(|(e, _)| *e != endpt);
+        callbacks.push((endpt, Arc::new(self)));
+
+        pjsip_inv_callback
+    }
+
+    fn lookup(inv: *mut pj::pjsip_inv_session) -> Option<Arc<Self>> {
+        let endpt = unsafe {
+            let dlg = (*inv).dlg;
+            if dlg.is_null() {
+                return None;
+            }
+            (*dlg).endpt as usize
+        };
 
-        wrapped::<T, F>
+        INV_CALLBACKS
+            .read()
+            .iter()
+            .find(|(e, _)| *e == endpt)
+            .map(|(_, cb)| cb.clone())
     }
+}
+
+/** Drop the callbacks registered for an endpoint. */
+pub(crate) fn unregister_inv_callback(sip_endpt: &PjSipEndpoint) {
+    let endpt = sip_endpt.as_mut_ptr() as usize;
+    INV_CALLBACKS.write().retain(|(e, _)| *e != endpt);
+}
+
+/* Look up the callbacks for the session and run `f` with them and a
+ * handle on the session. */
+unsafe fn dispatch<R>(
+    inv_ptr: *mut pj::pjsip_inv_session,
+    f: impl FnOnce(&PjSipInvCallback, &mut PjSipInvSession) -> Option<R>,
+) -> Option<R> {
+    let cb = PjSipInvCallback::lookup(inv_ptr)?;
+
+    f(&cb, &mut PjSipInvSession::from(inv_ptr))
+}
+
+unsafe extern "C" fn on_state_changed(
+    inv_ptr: *mut pj::pjsip_inv_session,
+    evt_ptr: *mut pj::pjsip_event,
+) {
+    dispatch(inv_ptr, |cb, inv| {
+        inv.record_state();
+        let on_state_changed = cb.on_state_changed.as_ref()?;
+        on_state_changed(inv, &mut PjSipEvent::from(evt_ptr));
+        Some(())
+    });
+}
+
+unsafe extern "C" fn on_new_session(
+    inv_ptr: *mut pj::pjsip_inv_session,
+    evt_ptr: *mut pj::pjsip_event,
+) {
+    dispatch(inv_ptr, |cb, inv| {
+        let on_new_session = cb.on_new_session.as_ref()?;
+        on_new_session(inv, &mut PjSipEvent::from(evt_ptr));
+        Some(())
+    });
+}
 
-    fn wrap_inv_status<F: Fn(&PjSipInvSession<T>, PjStatus)>(
-        _: F,
-    ) -> unsafe extern "C" fn(inv: *mut pj::pjsip_inv_session, status: pj::pj_status_t) {
-        assert!(std::mem::size_of::<F>() == 0);
+unsafe extern "C" fn on_tsx_state_changed(
+    inv_ptr: *mut pj::pjsip_inv_session,
+    tsx_ptr: *mut pj::pjsip_transaction,
+    evt_ptr: *mut pj::pjsip_event,
+) {
+    dispatch(inv_ptr, |cb, inv| {
+        let on_tsx_state_changed = cb.on_tsx_state_changed.as_ref()?;
+        on_tsx_state_changed(
+            inv,
+            &mut PjSipTransaction::from(tsx_ptr),
+            &mut PjSipEvent::from(evt_ptr),
+        );
+        Some(())
+    });
+}
 
-        unsafe extern "C" fn wrapped<T, F: Fn(&PjSipInvSession<T>, PjStatus)>(
-            inv_ptr: *mut pj::pjsip_inv_session,
-            status: pj::pj_status_t,
-        ) {
-            let mut inv = PjSipInvSession::from(inv_ptr);
-            let status = PjStatus::new(status);
-            std::mem::transmute::<_, &F>(&())(&mut inv, status);
-            std::mem::forget(inv);
+/* The answer is copied into the session's pool by pjsip, so the Rust owned
+ * SDP can be dropped once it has been set. */
+fn set_sdp_answer(inv: &mut PjSipInvSession, answer: Option<PjMediaSdpSession>) {
+    if let Some(answer) = answer {
+        if let Err(err) = inv.set_sdp_answer(&answer) {
+            tracing::error!("Failed to set SDP answer: {err}");
         }
+    }
+}
 
-        wrapped::<T, F>
+unsafe extern "C" fn on_rx_offer(
+    inv_ptr: *mut pj::pjsip_inv_session,
+    offer: *const pj::pjmedia_sdp_session,
+) {
+    dispatch(inv_ptr, |cb, inv| {
+        let on_rx_offer = cb.on_rx_offer.as_ref()?;
+        let answer = on_rx_offer(inv, &PjMediaSdpSessionRef::from(offer));
+        set_sdp_answer(inv, answer);
+        Some(())
+    });
+}
+
+unsafe extern "C" fn on_rx_offer2(
+    inv_ptr: *mut pj::pjsip_inv_session,
+    param: *mut pj::pjsip_inv_on_rx_offer_cb_param,
+) {
+    dispatch(inv_ptr, |cb, inv| {
+        let on_rx_offer2 = cb.on_rx_offer2.as_ref()?;
+        let answer = on_rx_offer2(
+            inv,
+            &PjMediaSdpSessionRef::from((*param).offer),
+            &PjSipRxData::from((*param).rdata as *mut pj::pjsip_rx_data),
+        );
+        set_sdp_answer(inv, answer);
+        Some(())
+    });
+}
+
+unsafe extern "C" fn on_rx_reinvite(
+    inv_ptr: *mut pj::pjsip_inv_session,
+    offer: *const pj::pjmedia_sdp_session,
+    rdata: *mut pj::pjsip_rx_data,
+) -> pj::pj_status_t {
+    let action = dispatch(inv_ptr, |cb, inv| {
+        let on_rx_reinvite = cb.on_rx_reinvite.as_ref()?;
+        let offer = (!offer.is_null()).then(|| PjMediaSdpSessionRef::from(offer));
+        Some(on_rx_reinvite(
+            inv,
+            offer.as_ref(),
+            &mut PjSipRxData::from(rdata),
+        ))
+    });
+
+    /* Any status other than success makes pjsip handle the re-INVITE */
+    match action {
+        Some(PjSipInvReinviteAction::Manual) => pj::pj_constants__PJ_SUCCESS as _,
+        _ => PJ_ENOTSUP,
     }
 }
+
+unsafe extern "C" fn on_create_offer(
+    inv_ptr: *mut pj::pjsip_inv_session,
+    p_offer: *mut *mut pj::pjmedia_sdp_session,
+) {
+    dispatch(inv_ptr, |cb, inv| {
+        let on_create_offer = cb.on_create_offer.as_ref()?;
+        let offer = on_create_offer(inv)?;
+
+        /* pjsip keeps the pointer, so the offer has to live in the
+         * session's pool rather than in the Rust owned SDP. */
+        let offer = pj::pjmedia_sdp_session_clone((*inv_ptr).pool_prov, offer.as_ptr());
+        if !offer.is_null() {
+            *p_offer = offer;
+        }
+        Some(())
+    });
+}
+
+unsafe extern "C" fn on_media_update(inv_ptr: *mut pj::pjsip_inv_session, status: pj::pj_status_t) {
+    dispatch(inv_ptr, |cb, inv| {
+        let on_media_update = cb.on_media_update.as_ref()?;
+        on_media_update(inv, PjStatus::new(status));
+        Some(())
+    });
+}
+
+unsafe extern "C" fn on_send_ack(
+    inv_ptr: *mut pj::pjsip_inv_session,
+    rdata: *mut pj::pjsip_rx_data,
+) {
+    dispatch(inv_ptr, |cb, inv| {
+        let on_send_ack = cb.on_send_ack.as_ref()?;
+        on_send_ack(inv, &mut PjSipRxData::from(rdata));
+        Some(())
+    });
+}
+
+unsafe extern "C" fn on_redirected(
+    inv_ptr: *mut pj::pjsip_inv_session,
+    target: *const pj::pjsip_uri,
+    evt_ptr: *const pj::pjsip_event,
+) -> pj::pjsip_redirect_op {
+    dispatch(inv_ptr, |cb, inv| {
+        let on_redirected = cb.on_redirected.as_ref()?;
+        Some(on_redirected(
+            inv,
+            &PjSipUriRef::from(target),
+            &PjSipEvent::from(evt_ptr as *mut pj::pjsip_event),
+        ))
+    })
+    .unwrap_or(PjSipRedirectOp::Reject)
+    .into()
+}
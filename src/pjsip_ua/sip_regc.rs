@@ -0,0 +1,386 @@
+use std::{
+    ffi::{c_void, CStr, CString},
+    sync::Arc,
+    time::Duration,
+};
+
+use parking_lot::Mutex;
+use pjproject_sys as pj;
+
+use crate::{
+    pj_str_to_string, Error, PjPool, PjSipCredInfo, PjSipEndpoint, PjSipTpSelector, PjSipUriRef,
+    PjStatus,
+};
+
+/* pjsip reports this expiration when the registrar gave none */
+const EXPIRATION_NOT_SPECIFIED: u32 = u32::MAX - 1;
+
+/** What to register: the registrar, the address of record and the
+ * contacts it should be reachable at. */
+pub struct PjSipRegistrationSetting {
+    registrar: CString,
+    aor: CString,
+    contacts: Vec<CString>,
+    expires: Duration,
+    delay_before_refresh: Option<Duration>,
+    headers: Vec<(CString, CString)>,
+    credentials: Vec<PjSipCredInfo>,
+}
+
+impl PjSipRegistrationSetting {
+    /** Register `aor`, e.g. `sip:1001@pbx.example.com`, at `registrar`,
+     * e.g. `sip:pbx.example.com`. Registers for an hour unless changed
+     * with `with_expires`. */
+    pub fn new<R: AsRef<CStr>, A: AsRef<CStr>>(registrar: R, aor: A) -> Self {
+        Self {
+            registrar: registrar.as_ref().to_owned(),
+            aor: aor.as_ref().to_owned(),
+            contacts: Vec::new(),
+            expires: Duration::from_secs(3600),
+            delay_before_refresh: None,
+            headers: Vec::new(),
+            credentials: Vec::new(),
+        }
+    }
+
+    /** Add a contact to register, e.g. `<sip:1001@192.0.2.10:5060>`. */
+    pub fn with_contact<S: AsRef<CStr>>(&mut self, contact: S) -> &mut Self {
+        self.contacts.push(contact.as_ref().to_owned());
+
+        self
+    }
+
+    pub fn with_expires(&mut self, expires: Duration) -> &mut Self {
+        self.expires = expires;
+
+        self
+    }
+
+    /** How long before the registration expires it is refreshed, pjsip
+     * defaults to 5 seconds. */
+    pub fn with_delay_before_refresh(&mut self, delay: Duration) -> &mut Self {
+        self.delay_before_refresh = Some(delay);
+
+        self
+    }
+
+    /** Add a header to every REGISTER, e.g. `User-Agent`. */
+    pub fn with_header<S: AsRef<CStr>, V: AsRef<CStr>>(&mut self, name: S, value: V) -> &mut Self {
+        self.headers
+            .push((name.as_ref().to_owned(), value.as_ref().to_owned()));
+
+        self
+    }
+
+    /** Add a credential to answer the registrar's challenge with. */
+    pub fn with_credential(&mut self, credential: PjSipCredInfo) -> &mut Self {
+        self.credentials.push(credential);
+
+        self
+    }
+}
+
+/** Outcome of a REGISTER, including refreshes and the unregistration. */
+pub struct PjSipRegistrationResult {
+    /** Set when no final response was received, e.g. on a timeout or a
+     * transport error. */
+    pub error: Option<Error>,
+    pub status_code: u16,
+    pub status_text: String,
+    /** How long the registrar keeps the registration, None if it did not
+     * say. */
+    pub expiration: Option<Duration>,
+    /** Contacts the registrar has for the address of record, ours and
+     * those of other devices. */
+    pub contacts: Vec<String>,
+    pub is_unregister: bool,
+}
+
+impl PjSipRegistrationResult {
+    pub fn is_success(&self) -> bool {
+        self.error.is_none() && (200..300).contains(&self.status_code)
+    }
+}
+
+type OnRegistration = Arc<dyn Fn(PjSipRegistrationResult) + Send + Sync>;
+
+/* Token of the regc. pjsip holds a reference of its own, released once it
+ * can no longer call back. */
+struct RegcToken {
+    state: Mutex<RegcTokenState>,
+}
+
+struct RegcTokenState {
+    /* Taken out on drop, later results are not reported */
+    on_registration: Option<OnRegistration>,
+    /* The registration was dropped while unregistering, the result of the
+     * unregistration destroys the regc */
+    owner_gone: bool,
+    destroyed: bool,
+}
+
+/** Client registration of an address of record at a registrar. Once
+ * registered, pjsip refreshes the registration before it expires, and
+ * dropping the registration unregisters.
+ *
+ * Every result, including those of refreshes, is passed to the callback
+ * on the thread polling the endpoint. */
+pub struct PjSipRegistration {
+    pjsip_regc: *mut pj::pjsip_regc,
+    token: Arc<RegcToken>,
+    registered: bool,
+}
+
+unsafe impl Send for PjSipRegistration {}
+unsafe impl Sync for PjSipRegistration {}
+
+impl PjSipRegistration {
+    pub fn new<F>(
+        endpoint: &PjSipEndpoint,
+        setting: &PjSipRegistrationSetting,
+        cb: F,
+    ) -> Result<Self, Error>
+    where
+        F: Fn(PjSipRegistrationResult) + Send + Sync + 'static,
+    {
+        let token = Arc::new(RegcToken {
+            state: Mutex::new(RegcTokenState {
+                on_registration: Some(Arc::new(cb)),
+                owner_gone: false,
+                destroyed: false,
+            }),
+        });
+
+        let mut pjsip_regc = std::ptr::null_mut();
+        let status = unsafe {
+            pj::pjsip_regc_create(
+                endpoint.as_mut_ptr(),
+                Arc::into_raw(token.clone()) as *mut c_void,
+                Some(on_regc),
+                &mut pjsip_regc,
+            )
+        };
+        if let Err(err) = PjStatus::result_for_status(status) {
+            unsafe { Arc::decrement_strong_count(Arc::as_ptr(&token)) };
+            return Err(err);
+        }
+
+        /* From here on dropping destroys the regc */
+        let registration = Self {
+            pjsip_regc,
+            token,
+            registered: false,
+        };
+        registration.init(setting)?;
+
+        Ok(registration)
+    }
+
+    fn init(&self, setting: &PjSipRegistrationSetting) -> Result<(), Error> {
+        let as_pj_str = |s: &CStr| unsafe { pj::pj_str(s.as_ptr() as *mut _) };
+
+        let contacts = setting
+            .contacts
+            .iter()
+            .map(|contact| as_pj_str(contact))
+            .collect::<Vec<_>>();
+        let status = unsafe {
+            pj::pjsip_regc_init(
+                self.pjsip_regc,
+                &as_pj_str(&setting.registrar),
+                &as_pj_str(&setting.aor),
+                &as_pj_str(&setting.aor),
+                contacts.len() as _,
+                contacts.as_ptr(),
+                setting.expires.as_secs().min(u32::MAX as u64 - 2) as _,
+            )
+        };
+        PjStatus::result_for_status(status)?;
+
+        if let Some(delay) = setting.delay_before_refresh {
+            let status = unsafe {
+                pj::pjsip_regc_set_delay_before_refresh(self.pjsip_regc, delay.as_secs() as _)
+            };
+            PjStatus::result_for_status(status)?;
+        }
+
+        if !setting.credentials.is_empty() {
+            let credentials = setting
+                .credentials
+                .iter()
+                .map(PjSipCredInfo::as_pj)
+                .collect::<Vec<_>>();
+            let status = unsafe {
+                pj::pjsip_regc_set_credentials(
+                    self.pjsip_regc,
+                    credentials.len() as _,
+                    credentials.as_ptr(),
+                )
+            };
+            PjStatus::result_for_status(status)?;
+        }
+
+        if !setting.headers.is_empty() {
+            /* pjsip clones the headers, they only need to live for the
+             * call */
+            let mut pool = PjPool::default_with_name(c"regc_hdr");
+            let mut hdr_list = unsafe { std::mem::zeroed::<pj::pjsip_hdr>() };
+            hdr_list.prev = &mut hdr_list;
+            hdr_list.next = &mut hdr_list;
+
+            for (name, value) in &setting.headers {
+                let hdr = unsafe {
+                    pj::pjsip_generic_string_hdr_create(
+                        pool.as_mut_ptr(),
+                        &as_pj_str(name),
+                        &as_pj_str(value),
+                    )
+                };
+                if hdr.is_null() {
+                    return Err(Error::Validation("Failed to create header".into()));
+                }
+                unsafe {
+                    pj::pj_list_insert_before(&mut hdr_list as *mut _ as *mut _, hdr as *mut _)
+                };
+            }
+
+            let status = unsafe { pj::pjsip_regc_add_headers(self.pjsip_regc, &hdr_list) };
+            PjStatus::result_for_status(status)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn as_mut_ptr(&self) -> *mut pj::pjsip_regc {
+        self.pjsip_regc
+    }
+
+    /** Send the REGISTER, and keep refreshing the registration until
+     * `unregister` or drop. */
+    pub fn register(&mut self) -> Result<(), Error> {
+        let mut tdata = std::ptr::null_mut();
+        let status = unsafe { pj::pjsip_regc_register(self.pjsip_regc, 1, &mut tdata) };
+        PjStatus::result_for_status(status)?;
+
+        let status = unsafe { pj::pjsip_regc_send(self.pjsip_regc, tdata) };
+        PjStatus::result_for_status(status)?;
+        self.registered = true;
+
+        Ok(())
+    }
+
+    /** Remove our contacts from the registrar and stop refreshing. */
+    pub fn unregister(&mut self) -> Result<(), Error> {
+        let mut tdata = std::ptr::null_mut();
+        let status = unsafe { pj::pjsip_regc_unregister(self.pjsip_regc, &mut tdata) };
+        PjStatus::result_for_status(status)?;
+
+        let status = unsafe { pj::pjsip_regc_send(self.pjsip_regc, tdata) };
+        PjStatus::result_for_status(status)?;
+        self.registered = false;
+
+        Ok(())
+    }
+
+    /* A request is in flight or its callback is running */
+    fn is_busy(&self) -> bool {
+        let mut info = unsafe { std::mem::zeroed::<pj::pjsip_regc_info>() };
+        let status = unsafe { pj::pjsip_regc_get_info(self.pjsip_regc, &mut info) };
+
+        status != pj::pj_constants__PJ_SUCCESS as _ || info.is_busy != 0
+    }
+
+    /** Send the REGISTER requests over the selected transport or
+     * listener. */
+    pub fn set_transport(&mut self, selector: &PjSipTpSelector) -> Result<(), Error> {
+        let status = unsafe { pj::pjsip_regc_set_transport(self.pjsip_regc, selector.as_ptr()) };
+
+        PjStatus::result_for_status(status)
+    }
+}
+
+impl Drop for PjSipRegistration {
+    fn drop(&mut self) {
+        self.token.state.lock().on_registration.take();
+
+        if self.registered {
+            /* Set before sending, the result may arrive at once */
+            self.token.state.lock().owner_gone = true;
+            match self.unregister() {
+                Ok(()) => return,
+                Err(err) => tracing::error!("Failed to unregister: {err}"),
+            }
+
+            let mut state = self.token.state.lock();
+            if state.destroyed {
+                return;
+            }
+            state.owner_gone = false;
+        }
+
+        /* pjsip defers destroying a busy regc until its callback returned,
+         * which still reads the token. Its reference is then leaked. */
+        let idle = !self.is_busy();
+
+        let status = unsafe { pj::pjsip_regc_destroy(self.pjsip_regc) };
+        if let Err(err) = PjStatus::result_for_status(status) {
+            tracing::error!("Failed to destroy registration: {err}");
+        }
+
+        if idle {
+            unsafe { Arc::decrement_strong_count(Arc::as_ptr(&self.token)) };
+        }
+    }
+}
+
+unsafe extern "C" fn on_regc(param: *mut pj::pjsip_regc_cbparam) {
+    let param = &*param;
+    let token = &*(param.token as *const RegcToken);
+
+    /* The callback may drop the registration, it runs unlocked */
+    let (on_registration, unregistered) = {
+        let mut state = token.state.lock();
+        let unregistered = param.is_unreg != 0 && state.owner_gone && !state.destroyed;
+        state.destroyed |= unregistered;
+
+        (state.on_registration.clone(), unregistered)
+    };
+
+    if let Some(cb) = on_registration {
+        report(param, &*cb);
+    }
+
+    /* Last result of a dropped registration, pjsip destroys the regc once
+     * this callback returned and calls back no more */
+    if unregistered {
+        let status = pj::pjsip_regc_destroy(param.regc);
+        if let Err(err) = PjStatus::result_for_status(status) {
+            tracing::error!("Failed to destroy registration: {err}");
+        }
+        Arc::decrement_strong_count(token as *const RegcToken);
+    }
+}
+
+unsafe fn report(
+    param: &pj::pjsip_regc_cbparam,
+    cb: &(dyn Fn(PjSipRegistrationResult) + Send + Sync),
+) {
+    let contacts = param.contact[..(param.contact_cnt.max(0) as usize).min(param.contact.len())]
+        .iter()
+        .filter(|contact| !contact.is_null())
+        .map(|contact| {
+            PjSipUriRef::from((**contact).uri as *const pj::pjsip_uri)
+                .print(pj::pjsip_uri_context_e_PJSIP_URI_IN_CONTACT_HDR)
+        })
+        .collect();
+
+    cb(PjSipRegistrationResult {
+        error: PjStatus::result_for_status(param.status).err(),
+        status_code: param.code as _,
+        status_text: pj_str_to_string(&param.reason),
+        expiration: (param.expiration != EXPIRATION_NOT_SPECIFIED)
+            .then(|| Duration::from_secs(param.expiration as _)),
+        contacts,
+        is_unregister: param.is_unreg != 0,
+    });
+}
@@ -0,0 +1,151 @@
+use std::{
+    ffi::CStr,
+    sync::{Arc, Mutex, MutexGuard},
+    time::{Duration, Instant},
+};
+
+use once_cell::sync::Lazy;
+use pjproject_rs::{
+    pj_init, pjlib_util_init, PjCachingPool, PjSipEndpoint, PjSipMethodId, PjSipModule,
+    PjSipModuleAction, PjSipModulePriority, PjSipTransportLoop, PjTimeVal,
+};
+use pjproject_sys as pj;
+
+const TARGET: &CStr = c"sip:uas@127.0.0.1;transport=loop-dgram";
+const FROM: &CStr = c"<sip:uac@127.0.0.1>";
+const TO: &CStr = c"<sip:uas@127.0.0.1>";
+
+/* pjsip allows one endpoint per process, the tests share it and run one
+ * at a time */
+struct Fixture {
+    endpt: Arc<PjSipEndpoint>,
+    transport: PjSipTransportLoop,
+}
+
+static FIXTURE: Lazy<Mutex<Fixture>> = Lazy::new(|| {
+    pj_init().unwrap();
+    pjlib_util_init().unwrap();
+
+    let endpt = Arc::new(PjSipEndpoint::new(PjCachingPool::default(), c"loop-test").unwrap());
+    endpt.init_tsx_layer_module().unwrap();
+    endpt.init_ua_module().unwrap();
+    let transport = endpt.loop_transport_start().unwrap();
+
+    /* The UAS: accepts OPTIONS and is busy for INVITE */
+    let uas_endpt = endpt.clone();
+    let mut uas = PjSipModule::new(c"mod-loop-uas").unwrap();
+    uas.with_priority(PjSipModulePriority::Application)
+        .with_on_rx_request(move |rdata| {
+            let status_code = match rdata.method_id() {
+                PjSipMethodId::Ack => return PjSipModuleAction::Handled,
+                PjSipMethodId::Options => 200,
+                PjSipMethodId::Invite => 486,
+                _ => 501,
+            };
+            uas_endpt
+                .respond_stateless(rdata, status_code, None::<&CStr>, &[], None)
+                .unwrap();
+
+            PjSipModuleAction::Handled
+        });
+    PjSipEndpoint::register_module(endpt.clone(), &mut uas).unwrap();
+    /* Registered for the rest of the process */
+    Box::leak(Box::new(uas));
+
+    Mutex::new(Fixture { endpt, transport })
+});
+
+fn fixture() -> MutexGuard<'static, Fixture> {
+    let fixture = FIXTURE.lock().unwrap_or_else(|err| err.into_inner());
+
+    /* Every test runs on its own thread, pjlib wants to know them */
+    unsafe {
+        if pj::pj_thread_is_registered() == 0 {
+            let desc = Box::leak(Box::new(std::mem::zeroed::<pj::pj_thread_desc>()));
+            let mut thread = std::ptr::null_mut();
+            pj::pj_thread_register(std::ptr::null(), desc.as_mut_ptr(), &mut thread);
+        }
+    }
+
+    fixture
+}
+
+/** Send `method` from the UAC and poll the endpoint until its final
+ * response. None when sending failed without calling back. */
+fn request(fixture: &Fixture, method: &CStr, timeout: Option<Duration>) -> Option<u16> {
+    let tdata = fixture
+        .endpt
+        .create_request(method, TARGET, FROM, TO, None)
+        .unwrap();
+
+    let (tx, rx) = flume::bounded(1);
+    let sent = fixture.endpt.send_request(tdata, timeout, move |response| {
+        tx.send(response.status_code).unwrap();
+    });
+    if sent.is_err() {
+        return None;
+    }
+
+    let deadline = Instant::now() + Duration::from_secs(40);
+    while Instant::now() < deadline {
+        if let Ok(status_code) = rx.try_recv() {
+            return Some(status_code);
+        }
+        fixture.endpt.handle_events(&PjTimeVal::new(0, 10)).unwrap();
+    }
+
+    panic!("No final response to {method:?}");
+}
+
+#[test]
+fn options() {
+    let fixture = fixture();
+
+    assert_eq!(request(&fixture, c"OPTIONS", None), Some(200));
+}
+
+#[test]
+fn invite_rejected() {
+    let fixture = fixture();
+
+    assert_eq!(request(&fixture, c"INVITE", None), Some(486));
+}
+
+#[test]
+fn options_with_delay() {
+    let fixture = fixture();
+    fixture
+        .transport
+        .set_delay(Duration::from_millis(200))
+        .unwrap();
+
+    let started = Instant::now();
+    let status_code = request(&fixture, c"OPTIONS", None);
+    fixture.transport.set_delay(Duration::ZERO).unwrap();
+
+    assert_eq!(status_code, Some(200));
+    assert!(started.elapsed() >= Duration::from_millis(200));
+}
+
+#[test]
+fn options_with_failure() {
+    let fixture = fixture();
+    fixture.transport.set_failure(true).unwrap();
+
+    let status_code = request(&fixture, c"OPTIONS", None);
+    fixture.transport.set_failure(false).unwrap();
+
+    /* Either refused right away or reported by the transaction */
+    assert!(matches!(status_code, None | Some(503)), "{status_code:?}");
+}
+
+#[test]
+fn options_with_discard() {
+    let fixture = fixture();
+    fixture.transport.set_discard(true).unwrap();
+
+    let status_code = request(&fixture, c"OPTIONS", Some(Duration::from_millis(500)));
+    fixture.transport.set_discard(false).unwrap();
+
+    assert_eq!(status_code, Some(408));
+}